@@ -0,0 +1,350 @@
+//! Asynchronous reading of JSON arrays and JSON Lines on top of Tokio.
+use crate::bytes_object::BytesObject;
+use crate::error::{RecordError, Result};
+use crate::reader::{ErrorPolicy, ReadState, ReaderBuilder};
+use futures_core::{ready, Stream};
+use serde::de::DeserializeOwned;
+use std::{
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// An asynchronous reader of JSON records.
+///
+/// The input may arrive in chunks that end anywhere, including in the middle
+/// of a record; records are only yielded once they are complete. Reading
+/// never blocks the executor.
+///
+/// An `AsyncReader` is built with
+/// [`ReaderBuilder::from_async_reader`](crate::ReaderBuilder::from_async_reader)
+/// and frames records exactly like a [`Reader`](crate::Reader) built from the
+/// same builder, with the same error policy and limits.
+#[derive(Debug)]
+pub struct AsyncReader<R> {
+    rdr: R,
+    state: ReadState,
+    buf: Box<[u8]>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncReader<R> {
+    pub(crate) fn new(builder: &ReaderBuilder, rdr: R) -> AsyncReader<R> {
+        AsyncReader {
+            rdr,
+            state: ReadState::new(builder),
+            buf: vec![0; builder.capacity].into_boxed_slice(),
+        }
+    }
+
+    /// Create a new asynchronous JSON array reader with a default
+    /// configuration.
+    ///
+    /// To read JSON Lines or customize reading, use
+    /// [`ReaderBuilder::from_async_reader`](crate::ReaderBuilder::from_async_reader).
+    pub fn from_reader(rdr: R) -> AsyncReader<R> {
+        ReaderBuilder::new().from_async_reader(rdr)
+    }
+
+    /// Returns a borrowed stream over deserialized records.
+    ///
+    /// Each item yielded by this stream is a `Result<D, Error>`. With the
+    /// default [`ErrorPolicy::Fail`], the stream ends after the first error;
+    /// otherwise malformed records are skipped and only errors that end
+    /// reading are yielded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use futures_util::StreamExt;
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// async fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = "{\"city\":\"Boston\"}\n{\"city\":\"Concord\"}\n";
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .from_async_reader(data.as_bytes());
+    ///     let mut records = rdr.deserialize::<serde_json::Value>();
+    ///     while let Some(record) = records.next().await {
+    ///         println!("{}", record?["city"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn deserialize<D: DeserializeOwned>(&mut self) -> DeserializeRecordsStream<'_, R, D> {
+        DeserializeRecordsStream {
+            rdr: self,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Returns an owned stream over deserialized records.
+    ///
+    /// This is like [`deserialize`](AsyncReader::deserialize), but the stream
+    /// takes ownership of the reader, so it can be moved into a task.
+    pub fn into_deserialize<D: DeserializeOwned>(self) -> DeserializeRecordsIntoStream<R, D> {
+        DeserializeRecordsIntoStream {
+            rdr: self,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Poll for the raw bytes of the next record.
+    ///
+    /// Records that cannot be delimited are handled according to the
+    /// reader's [`ErrorPolicy`].
+    pub fn poll_read_object(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<BytesObject>>> {
+        loop {
+            match self.state.next_object() {
+                Ok(Some(obj)) => return Poll::Ready(Some(Ok(obj))),
+                Ok(None) if self.state.is_done() => return Poll::Ready(None),
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+            let mut buf = ReadBuf::new(&mut self.buf);
+            match Pin::new(&mut self.rdr).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(self.state.fail(err)))),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => self.state.end_input(),
+                Poll::Ready(Ok(())) => {
+                    if let Err(err) = self.state.push(buf.filled()) {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The policy for malformed records.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.state.error_policy()
+    }
+
+    /// The number of malformed records skipped so far.
+    pub fn skipped(&self) -> u64 {
+        self.state.skipped()
+    }
+
+    /// The malformed records skipped so far with [`ErrorPolicy::Collect`].
+    pub fn errors(&self) -> &[RecordError] {
+        self.state.errors()
+    }
+
+    /// Take the malformed records collected so far, leaving none behind.
+    pub fn take_errors(&mut self) -> Vec<RecordError> {
+        self.state.take_errors()
+    }
+
+    fn poll_deserialize<D: DeserializeOwned>(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<D>>> {
+        loop {
+            let obj = match ready!(self.poll_read_object(cx)) {
+                Some(Ok(obj)) => obj,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            };
+            match self.state.deserialize(obj) {
+                Ok(Some(record)) => return Poll::Ready(Some(Ok(record))),
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+/// A borrowed stream over deserialized records.
+///
+/// The lifetime parameter `'r` refers to the lifetime of the underlying
+/// [`AsyncReader`].
+#[derive(Debug)]
+pub struct DeserializeRecordsStream<'r, R, D> {
+    rdr: &'r mut AsyncReader<R>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<'r, R: AsyncRead + Unpin, D: DeserializeOwned> Stream for DeserializeRecordsStream<'r, R, D> {
+    type Item = Result<D>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<D>>> {
+        self.get_mut().rdr.poll_deserialize(cx)
+    }
+}
+
+/// An owned stream over deserialized records.
+#[derive(Debug)]
+pub struct DeserializeRecordsIntoStream<R, D> {
+    rdr: AsyncReader<R>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<R: AsyncRead + Unpin, D: DeserializeOwned> DeserializeRecordsIntoStream<R, D> {
+    /// Return the underlying reader.
+    pub fn into_reader(self) -> AsyncReader<R> {
+        self.rdr
+    }
+}
+
+impl<R: AsyncRead + Unpin, D: DeserializeOwned> Stream for DeserializeRecordsIntoStream<R, D> {
+    type Item = Result<D>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<D>>> {
+        self.get_mut().rdr.poll_deserialize(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use crate::async_reader::AsyncReader;
+    use crate::reader::{ErrorPolicy, ReaderBuilder};
+    use futures_util::StreamExt;
+    use serde::Deserialize;
+    use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        city: String,
+        country: String,
+        #[serde(rename = "popcount")]
+        population: u64,
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {
+                city: "Boston".to_string(),
+                country: "United States".to_string(),
+                population: 4628910,
+            },
+            Row {
+                city: "Concord".to_string(),
+                country: "United States".to_string(),
+                population: 42695,
+            },
+        ]
+    }
+
+    const JSON_DATA: &str = r#"[{"city":"Boston","country":"United States","popcount":4628910},{"city":"Concord","country":"United States","popcount":42695}]"#;
+    const JSON_LINES_DATA: &str = "{\"city\":\"Boston\",\"country\":\"United States\",\"popcount\":4628910}\n{\"city\":\"Concord\",\"country\":\"United States\",\"popcount\":42695}\n";
+
+    /// Yields its data a few bytes at a time, returning `Pending` in between.
+    struct Trickle {
+        data: &'static [u8],
+        pending: bool,
+    }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = self.data.len().min(3).min(buf.remaining());
+            buf.put_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn example_json() -> Result<(), Box<dyn Error>> {
+        let mut rdr = AsyncReader::from_reader(JSON_DATA.as_bytes());
+        let data = rdr
+            .deserialize::<Row>()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn example_json_lines_in_pieces() -> Result<(), Box<dyn Error>> {
+        let rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_async_reader(Trickle {
+                data: JSON_LINES_DATA.as_bytes(),
+                pending: false,
+            });
+        let mut data = Vec::new();
+        let mut records = rdr.into_deserialize::<Row>();
+        while let Some(record) = records.next().await {
+            data.push(record?);
+        }
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_from_a_pipe() -> Result<(), Box<dyn Error>> {
+        let (mut tx, rx) = tokio::io::duplex(16);
+        let writer = tokio::spawn(async move {
+            for chunk in JSON_DATA.as_bytes().chunks(7) {
+                tx.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+        let data = AsyncReader::from_reader(rx)
+            .into_deserialize::<Row>()
+            .map(|record| record.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        writer.await?;
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stops_after_error() {
+        let mut rdr = AsyncReader::from_reader(&b"[1, {\"a\": 2}, 3]"[..]);
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn honours_the_reader_builder() {
+        let data = "1\n{\"a\":\n3\n\"x\"\n5\n6\n";
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .error_policy(ErrorPolicy::Collect)
+            .max_records(5)
+            .buffer_capacity(2)
+            .from_async_reader(Trickle {
+                data: data.as_bytes(),
+                pending: false,
+            });
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert_eq!(results[1].as_ref().unwrap(), &3);
+        assert_eq!(results[2].as_ref().unwrap(), &5);
+        assert!(results[3].is_err());
+        assert_eq!(rdr.skipped(), 2);
+        assert_eq!(rdr.errors()[1].index(), 3);
+
+        let mut rdr = ReaderBuilder::new()
+            .max_input_bytes(4)
+            .from_async_reader(&b"[1, 2, 3]"[..]);
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>().await;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Asynchronous writing of JSON arrays and JSON Lines on top of Tokio.
+use crate::error::{Error, Result};
+use crate::writer::{Writer, WriterBuilder};
+use futures_core::ready;
+use futures_sink::Sink;
+use serde::Serialize;
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::AsyncWrite;
+
+/// The amount of serialized data buffered before it is written out.
+const BUFFER_CAPACITY: usize = 8 * (1 << 10);
+
+/// Create a future that completes when `f` returns `Poll::Ready`.
+///
+/// This stands in for `std::future::poll_fn`, which is newer than the
+/// crate's minimum supported Rust version.
+fn poll_fn<T, F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin>(f: F) -> PollFn<F> {
+    PollFn(f)
+}
+
+struct PollFn<F>(F);
+
+impl<T, F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin> Future for PollFn<F> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// An asynchronous writer of JSON records.
+///
+/// Records are serialized into an internal buffer, which is written to the
+/// underlying writer once it fills up, on [`flush`](AsyncWriter::flush) and
+/// on [`close`](AsyncWriter::close). Writing never blocks the executor.
+///
+/// `AsyncWriter` also implements [`Sink`], so records can be forwarded from a
+/// stream with `SinkExt::send_all`. The sink applies backpressure by only
+/// accepting a new record once the buffer has room for it.
+///
+/// # Shutdown
+///
+/// Unlike [`Writer`], an `AsyncWriter` cannot finish its output when it is
+/// dropped, since that would require writing from a synchronous `Drop`.
+/// Callers must call [`close`](AsyncWriter::close) (or `SinkExt::close`)
+/// and await it: this writes the closing `]` of an array, flushes all
+/// buffered data and shuts the underlying writer down. A writer that is
+/// dropped without being closed loses its buffered records, and in debug
+/// builds a warning is printed to stderr.
+#[derive(Debug)]
+pub struct AsyncWriter<W: AsyncWrite + Unpin> {
+    wtr: Option<W>,
+    buf: Writer<Vec<u8>>,
+    written: usize,
+    state: CloseState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CloseState {
+    Open,
+    Closing,
+    Closed,
+}
+
+impl<W: AsyncWrite + Unpin> Drop for AsyncWriter<W> {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions)
+            && self.wtr.is_some()
+            && self.state != CloseState::Closed
+            && !std::thread::panicking()
+        {
+            eprintln!(
+                "json_arrays: AsyncWriter dropped without calling `close().await`; \
+                 the output is incomplete"
+            );
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> AsyncWriter<W> {
+        AsyncWriter {
+            wtr: Some(wtr),
+            buf: builder.from_writer(Vec::with_capacity(BUFFER_CAPACITY)),
+            written: 0,
+            state: CloseState::Open,
+        }
+    }
+
+    /// Create a new asynchronous JSON array writer with a default
+    /// configuration.
+    ///
+    /// To write JSON Lines, use
+    /// [`WriterBuilder::from_async_writer`](crate::WriterBuilder::from_async_writer).
+    pub fn from_writer(wtr: W) -> AsyncWriter<W> {
+        WriterBuilder::new().from_async_writer(wtr)
+    }
+
+    /// Serialize a single record using Serde.
+    ///
+    /// If the internal buffer is full, this waits until it has been written
+    /// to the underlying writer.
+    pub async fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
+        poll_fn(|cx| self.poll_ready_record(cx)).await?;
+        self.push_record(record)
+    }
+
+    /// Write all buffered records to the underlying writer and flush it.
+    pub async fn flush(&mut self) -> Result<()> {
+        poll_fn(|cx| self.poll_flush_all(cx)).await
+    }
+
+    /// Finish the output and shut the underlying writer down.
+    ///
+    /// For arrays this writes the closing `]`. Closing an already closed
+    /// writer does nothing.
+    pub async fn close(&mut self) -> Result<()> {
+        poll_fn(|cx| self.poll_close_all(cx)).await
+    }
+
+    /// Whether [`close`](AsyncWriter::close) has completed.
+    pub fn is_closed(&self) -> bool {
+        self.state == CloseState::Closed
+    }
+
+    /// Return a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.wtr.as_ref().unwrap()
+    }
+
+    /// Return a mutable reference to the underlying writer.
+    ///
+    /// Writing to the underlying writer directly may corrupt the JSON
+    /// output.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.wtr.as_mut().unwrap()
+    }
+
+    /// Close the writer and return the underlying writer.
+    pub async fn into_inner(mut self) -> Result<W> {
+        self.close().await?;
+        Ok(self.wtr.take().unwrap())
+    }
+
+    fn push_record<S: Serialize>(&mut self, record: S) -> Result<()> {
+        if self.state != CloseState::Open {
+            return Err(io::Error::new(io::ErrorKind::Other, "writer is closed").into());
+        }
+        self.buf.serialize(record)
+    }
+
+    fn poll_ready_record(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.buf.get_ref().len() >= BUFFER_CAPACITY {
+            ready!(self.poll_write_buf(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.written < self.buf.get_ref().len() {
+            let pending = &self.buf.get_ref()[self.written..];
+            match ready!(Pin::new(self.wtr.as_mut().unwrap()).poll_write(cx, pending)) {
+                Ok(0) => return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero).into())),
+                Ok(n) => self.written += n,
+                Err(err) => return Poll::Ready(Err(err.into())),
+            }
+        }
+        self.buf.get_mut().clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush_all(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_write_buf(cx))?;
+        let wtr = Pin::new(self.wtr.as_mut().unwrap());
+        Poll::Ready(ready!(wtr.poll_flush(cx)).map_err(Error::from))
+    }
+
+    fn poll_close_all(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.state == CloseState::Open {
+            self.buf.close()?;
+            self.state = CloseState::Closing;
+        }
+        if self.state == CloseState::Closing {
+            ready!(self.poll_write_buf(cx))?;
+            ready!(Pin::new(self.wtr.as_mut().unwrap()).poll_shutdown(cx))?;
+            self.state = CloseState::Closed;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin, S: Serialize> Sink<S> for AsyncWriter<W> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_ready_record(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, record: S) -> Result<()> {
+        self.get_mut().push_record(record)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_flush_all(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_close_all(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+
+    use crate::async_writer::AsyncWriter;
+    use crate::writer::WriterBuilder;
+    use futures_util::{stream, SinkExt, StreamExt};
+    use serde::Serialize;
+    use tokio::io::AsyncReadExt;
+
+    #[derive(Serialize)]
+    struct Row<'a> {
+        city: &'a str,
+        country: &'a str,
+        #[serde(rename = "popcount")]
+        population: u64,
+    }
+
+    #[tokio::test]
+    async fn example_json() -> Result<(), Box<dyn Error>> {
+        let mut wtr = AsyncWriter::from_writer(vec![]);
+        wtr.serialize(Row {
+            city: "Boston",
+            country: "United States",
+            population: 4628910,
+        })
+        .await?;
+        wtr.serialize(Row {
+            city: "Concord",
+            country: "United States",
+            population: 42695,
+        })
+        .await?;
+
+        let data = String::from_utf8(wtr.into_inner().await?)?;
+        assert_eq!(
+            data,
+            r#"[{"city":"Boston","country":"United States","popcount":4628910},{"city":"Concord","country":"United States","popcount":42695}]"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sink_json_lines() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .from_async_writer(vec![]);
+        let mut records = stream::iter(0..3).map(Ok);
+        wtr.send_all(&mut records).await?;
+        SinkExt::<u32>::close(&mut wtr).await?;
+        assert!(wtr.is_closed());
+        assert_eq!(wtr.get_ref(), b"0\n1\n2");
+        assert!(wtr.serialize(3).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn applies_backpressure() -> Result<(), Box<dyn Error>> {
+        let (tx, mut rx) = tokio::io::duplex(64);
+        let reader = tokio::spawn(async move {
+            let mut data = String::new();
+            rx.read_to_string(&mut data).await.unwrap();
+            data
+        });
+        let mut wtr = AsyncWriter::from_writer(tx);
+        for n in 0..10_000u32 {
+            wtr.serialize(n).await?;
+        }
+        wtr.close().await?;
+        drop(wtr);
+
+        let expected = serde_json::to_string(&(0..10_000).collect::<Vec<u32>>())?;
+        assert_eq!(reader.await?, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+//! BGZF (blocked gzip) output with a virtual-offset record index.
+//!
+//! BGZF files are ordinary multi-member gzip files, so `gunzip` and every
+//! other gzip reader can decompress them. Each member is a block of at most
+//! 64 KiB, which makes it possible to address any byte of the uncompressed
+//! stream with a *virtual offset*: the compressed offset of its block shifted
+//! left by 16 bits, plus the offset within the uncompressed block.
+//!
+//! A [`BgzfWriter`] records the virtual offset of every record in a
+//! [`BgzfIndex`], and a [`BgzfReader`] uses the index to decompress only the
+//! blocks holding the requested record.
+use crate::decoder::is_whitespace;
+use crate::error::{Error, Result};
+use crate::writer::{Writer, WriterBuilder};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression, Crc};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The largest amount of uncompressed data stored in one block.
+///
+/// This matches htslib and leaves room for incompressible data.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+const BLOCK_HEADER_SIZE: usize = 18;
+const BLOCK_FOOTER_SIZE: usize = 8;
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+const INDEX_MAGIC: &[u8; 4] = b"BGZI";
+const INDEX_VERSION: u8 = 1;
+
+/// The virtual offsets of the records in a BGZF file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BgzfIndex {
+    is_json_lines: bool,
+    offsets: Vec<u64>,
+}
+
+impl BgzfIndex {
+    /// The number of indexed records.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Whether the indexed records are in JSON Lines format.
+    pub fn is_json_lines(&self) -> bool {
+        self.is_json_lines
+    }
+
+    /// The virtual offset of record `n`.
+    ///
+    /// The offset points at the separator preceding the record (`[`, `,` or
+    /// a newline), or at the record itself if it has none.
+    pub fn get(&self, n: usize) -> Option<u64> {
+        self.offsets.get(n).copied()
+    }
+
+    /// Write the index in a compact binary format.
+    pub fn write_to<W: io::Write>(&self, mut wtr: W) -> Result<()> {
+        wtr.write_all(INDEX_MAGIC)?;
+        wtr.write_all(&[INDEX_VERSION, u8::from(self.is_json_lines)])?;
+        wtr.write_all(&(self.offsets.len() as u64).to_le_bytes())?;
+        for offset in &self.offsets {
+            wtr.write_all(&offset.to_le_bytes())?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Read an index written by [`write_to`](BgzfIndex::write_to).
+    pub fn read_from<R: io::Read>(mut rdr: R) -> Result<BgzfIndex> {
+        let mut header = [0; 14];
+        rdr.read_exact(&mut header)?;
+        if &header[..4] != INDEX_MAGIC || header[4] != INDEX_VERSION {
+            return Err(invalid_data("not a BGZF record index"));
+        }
+        let len = u64::from_le_bytes(header[6..].try_into().unwrap());
+        let mut offsets = Vec::new();
+        let mut buf = [0; 8];
+        for _ in 0..len {
+            rdr.read_exact(&mut buf)?;
+            offsets.push(u64::from_le_bytes(buf));
+        }
+        Ok(BgzfIndex {
+            is_json_lines: header[5] & 1 == 1,
+            offsets,
+        })
+    }
+}
+
+/// A writer that writes records as BGZF and indexes their virtual offsets.
+///
+/// Create one with
+/// [`WriterBuilder::from_bgzf_writer`](crate::WriterBuilder::from_bgzf_writer)
+/// and call [`finish`](BgzfWriter::finish) once all records are written to
+/// get the underlying writer and the record index back. If the writer is
+/// dropped without being finished, the stream is finished on a best-effort
+/// basis and any errors are ignored.
+#[derive(Debug)]
+pub struct BgzfWriter<W: io::Write> {
+    wtr: Writer<BgzfEncoder<W>>,
+    index: BgzfIndex,
+}
+
+impl<W: io::Write> BgzfWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> BgzfWriter<W> {
+        BgzfWriter {
+            wtr: builder.from_writer(BgzfEncoder::new(wtr)),
+            index: BgzfIndex {
+                is_json_lines: builder.is_json_lines,
+                offsets: Vec::new(),
+            },
+        }
+    }
+
+    /// Serialize a single record using Serde and record its virtual offset.
+    pub fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
+        let offset = self.wtr.get_ref().virtual_offset();
+        self.wtr.serialize(record)?;
+        self.index.offsets.push(offset);
+        Ok(())
+    }
+
+    /// The index of the records written so far.
+    pub fn index(&self) -> &BgzfIndex {
+        &self.index
+    }
+
+    /// Flush the current block to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+
+    /// Close the JSON framing, write the BGZF end-of-file marker and return
+    /// the underlying writer together with the record index.
+    pub fn finish(self) -> Result<(W, BgzfIndex)> {
+        let encoder = self.wtr.into_inner()?;
+        Ok((encoder.finish()?, self.index))
+    }
+}
+
+/// An `io::Write` that compresses its input into BGZF blocks.
+#[derive(Debug)]
+struct BgzfEncoder<W: io::Write> {
+    inner: Option<W>,
+    buf: Vec<u8>,
+    compressed_offset: u64,
+}
+
+impl<W: io::Write> Drop for BgzfEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.finish_blocks();
+        }
+    }
+}
+
+impl<W: io::Write> BgzfEncoder<W> {
+    fn new(wtr: W) -> BgzfEncoder<W> {
+        BgzfEncoder {
+            inner: Some(wtr),
+            buf: Vec::with_capacity(BLOCK_DATA_SIZE),
+            compressed_offset: 0,
+        }
+    }
+
+    /// The virtual offset of the next byte written.
+    fn virtual_offset(&self) -> u64 {
+        (self.compressed_offset << 16) | self.buf.len() as u64
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.finish_blocks()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn finish_blocks(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(&EOF_BLOCK)?;
+        inner.flush()
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let mut cdata = deflate(&self.buf, Compression::default())?;
+        if cdata.len() > MAX_BLOCK_SIZE - BLOCK_HEADER_SIZE - BLOCK_FOOTER_SIZE {
+            cdata = deflate(&self.buf, Compression::none())?;
+        }
+        let block_size = BLOCK_HEADER_SIZE + cdata.len() + BLOCK_FOOTER_SIZE;
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+
+        let mut block = Vec::with_capacity(block_size);
+        block.extend_from_slice(&[
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ]);
+        block.extend_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        block.extend_from_slice(&cdata);
+        block.extend_from_slice(&crc.sum().to_le_bytes());
+        block.extend_from_slice(&(self.buf.len() as u32).to_le_bytes());
+        self.inner.as_mut().unwrap().write_all(&block)?;
+
+        self.compressed_offset += block_size as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for BgzfEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_DATA_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+fn deflate(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), level);
+    io::Write::write_all(&mut encoder, data)?;
+    encoder.finish()
+}
+
+/// A reader that uses a [`BgzfIndex`] to read single records from a BGZF
+/// file without decompressing the blocks before them.
+#[derive(Debug)]
+pub struct BgzfReader<R> {
+    rdr: R,
+    index: BgzfIndex,
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Create a reader for a BGZF file written together with `index`.
+    pub fn new(rdr: R, index: BgzfIndex) -> BgzfReader<R> {
+        BgzfReader { rdr, index }
+    }
+
+    /// The number of records in the file.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether the file holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Read and deserialize record `n`.
+    ///
+    /// Returns `Ok(None)` if `n` is out of bounds.
+    pub fn get<D: DeserializeOwned>(&mut self, n: usize) -> Result<Option<D>> {
+        let offset = match self.index.get(n) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        self.rdr.seek(SeekFrom::Start(offset >> 16))?;
+        let mut buf = Vec::new();
+        self.read_block(&mut buf)?;
+        let mut pos = (offset & 0xffff) as usize;
+
+        loop {
+            while pos < buf.len() && is_whitespace(buf[pos]) {
+                pos += 1;
+            }
+            if pos < buf.len() {
+                break;
+            }
+            if !self.read_block(&mut buf)? {
+                return Err(invalid_data("unexpected end of BGZF data"));
+            }
+        }
+        if !self.index.is_json_lines && (buf[pos] == b'[' || buf[pos] == b',') {
+            pos += 1;
+        }
+
+        loop {
+            let mut iter = serde_json::Deserializer::from_slice(&buf[pos..]).into_iter();
+            let result = iter.next();
+            let consumed_all = pos + iter.byte_offset() == buf.len();
+            // The record may continue in the next block, which includes a
+            // number that happens to end exactly at the end of this one.
+            let need_more = match &result {
+                Some(Err(err)) => err.is_eof(),
+                Some(Ok(_)) => consumed_all,
+                None => true,
+            };
+            if need_more && self.read_block(&mut buf)? {
+                continue;
+            }
+            return match result {
+                Some(result) => Ok(Some(result?)),
+                None => Err(invalid_data("unexpected end of BGZF data")),
+            };
+        }
+    }
+
+    /// Decompress the next non-empty block and append it to `buf`.
+    ///
+    /// Returns `false` at the end of the file.
+    fn read_block(&mut self, buf: &mut Vec<u8>) -> Result<bool> {
+        loop {
+            let mut header = [0; 12];
+            match self.rdr.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(err) => return Err(err.into()),
+            }
+            if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+                return Err(invalid_data("not a BGZF block"));
+            }
+            let mut extra = vec![0; usize::from(u16::from_le_bytes([header[10], header[11]]))];
+            self.rdr.read_exact(&mut extra)?;
+            let block_size = block_size(&extra)?;
+            if block_size < header.len() + extra.len() + BLOCK_FOOTER_SIZE {
+                return Err(invalid_data("malformed BGZF block"));
+            }
+            let mut rest = vec![0; block_size - header.len() - extra.len()];
+            self.rdr.read_exact(&mut rest)?;
+
+            let (cdata, footer) = rest.split_at(rest.len() - BLOCK_FOOTER_SIZE);
+            let len = buf.len();
+            DeflateDecoder::new(cdata).read_to_end(buf)?;
+            let mut crc = Crc::new();
+            crc.update(&buf[len..]);
+            if footer[..4] != crc.sum().to_le_bytes() {
+                return Err(invalid_data("BGZF block checksum mismatch"));
+            }
+            if buf.len() > len {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Find the total block size in the `BC` subfield of a gzip extra field.
+fn block_size(mut extra: &[u8]) -> Result<usize> {
+    while extra.len() >= 4 {
+        let len = usize::from(u16::from_le_bytes([extra[2], extra[3]]));
+        if extra[..2] == *b"BC" && len == 2 && extra.len() >= 6 {
+            return Ok(usize::from(u16::from_le_bytes([extra[4], extra[5]])) + 1);
+        }
+        extra = &extra[(4 + len).min(extra.len())..];
+    }
+    Err(invalid_data("gzip member has no BGZF block size"))
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::{Cursor, Read};
+
+    use crate::bgzf::{BgzfIndex, BgzfReader};
+    use crate::writer::WriterBuilder;
+    use flate2::read::MultiGzDecoder;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Row {
+        id: u64,
+        text: String,
+    }
+
+    fn rows(n: u64) -> Vec<Row> {
+        (0..n)
+            .map(|id| Row {
+                id,
+                text: format!("{}", id).repeat(50),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn output_is_plain_gzip() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new().from_bgzf_writer(vec![]);
+        for row in rows(5000) {
+            wtr.serialize(row)?;
+        }
+        let (data, index) = wtr.finish()?;
+        assert_eq!(index.len(), 5000);
+
+        let mut json = Vec::new();
+        MultiGzDecoder::new(&data[..]).read_to_end(&mut json)?;
+        assert_eq!(json, serde_json::to_vec(&rows(5000))?);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_records_by_virtual_offset() -> Result<(), Box<dyn Error>> {
+        for json_lines in [false, true] {
+            let mut wtr = WriterBuilder::new()
+                .json_lines(json_lines)
+                .from_bgzf_writer(vec![]);
+            for row in rows(5000) {
+                wtr.serialize(row)?;
+            }
+            let (data, index) = wtr.finish()?;
+            assert!(index.get(4999).unwrap() >> 16 > 0);
+
+            let mut rdr = BgzfReader::new(Cursor::new(data), index);
+            let expected = rows(5000);
+            for n in [0, 1, 1234, 2500, 4999] {
+                assert_eq!(rdr.get::<Row>(n)?.as_ref(), Some(&expected[n]));
+            }
+            assert_eq!(rdr.get::<Row>(5000)?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_numbers_split_across_blocks() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new().from_bgzf_writer(vec![]);
+        for n in 0..100_000u64 {
+            wtr.serialize(n * 1_000_003)?;
+        }
+        let (data, index) = wtr.finish()?;
+        let boundaries = (1..index.len())
+            .filter(|&n| index.get(n).unwrap() >> 16 != index.get(n - 1).unwrap() >> 16)
+            .collect::<Vec<_>>();
+        assert!(!boundaries.is_empty());
+
+        let mut rdr = BgzfReader::new(Cursor::new(data), index);
+        for n in boundaries {
+            for n in [n - 1, n] {
+                assert_eq!(rdr.get::<u64>(n)?, Some(n as u64 * 1_000_003));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn index_roundtrip() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .from_bgzf_writer(vec![]);
+        wtr.serialize("a")?;
+        wtr.serialize("b")?;
+        let (_, index) = wtr.finish()?;
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf)?;
+        assert_eq!(BgzfIndex::read_from(&buf[..])?, index);
+        assert!(BgzfIndex::read_from(&b"nope"[..]).is_err());
+        Ok(())
+    }
+}
@@ -1 +1,47 @@
+use crate::error::Result;
+use serde::Deserialize;
 
+/// The raw bytes of a single JSON record.
+///
+/// A `BytesObject` holds exactly one JSON value, without the surrounding
+/// array punctuation or line terminator, together with the byte offset at
+/// which it started in the input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BytesObject {
+    bytes: Vec<u8>,
+    position: u64,
+}
+
+impl BytesObject {
+    /// Create a new empty object.
+    pub fn new() -> BytesObject {
+        BytesObject::default()
+    }
+
+    pub(crate) fn from_parts(bytes: Vec<u8>, position: u64) -> BytesObject {
+        BytesObject { bytes, position }
+    }
+
+    /// The raw bytes of the record.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Consume the object and return its raw bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The byte offset of the start of the record in the input.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Deserialize the record using Serde.
+    ///
+    /// The result may borrow from the object, so `&str` fields are supported
+    /// as long as they contain no escape sequences.
+    pub fn deserialize<'de, D: Deserialize<'de>>(&'de self) -> Result<D> {
+        Ok(serde_json::from_slice(&self.bytes)?)
+    }
+}
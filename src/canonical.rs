@@ -0,0 +1,459 @@
+//! Canonical JSON serialization as defined by RFC 8785, the JSON
+//! Canonicalization Scheme (JCS).
+//!
+//! Canonical JSON has exactly one serialization for a given value, so it can
+//! be hashed or signed. Object members are sorted by the UTF-16 code units
+//! of their names, numbers are formatted as in ECMAScript, strings use the
+//! minimal escaping of JSON, and there is no insignificant whitespace.
+//!
+//! Numbers are IEEE 754 doubles in JCS, so integers beyond 2^53 lose
+//! precision. Non-finite floats are written as `null`, as serde_json does.
+//!
+//! The lighter [`sort_keys`](crate::WriterBuilder::sort_keys) option of
+//! the writer also uses this module. It only sorts object members, by their
+//! names as Rust strings, and otherwise writes records as serde_json does.
+use crate::decoder::is_whitespace;
+use serde::ser::Error as _;
+use serde::Serialize;
+use serde_json::Value;
+use std::{borrow::Cow, io};
+
+/// Serialize `value` as canonical JSON into a byte vector.
+///
+/// # Example
+///
+/// ```
+/// use std::error::Error;
+///
+/// use json_arrays::canonical;
+/// use serde_json::json;
+///
+/// # fn main() { example().unwrap(); }
+/// fn example() -> Result<(), Box<dyn Error>> {
+///     let value = json!({"b": [1.50, 1e21], "a": "\u{20ac}"});
+///     assert_eq!(canonical::to_vec(&value)?, "{\"a\":\"€\",\"b\":[1.5,1e+21]}".as_bytes());
+///     Ok(())
+/// }
+/// ```
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(128);
+    to_writer(&mut out, value)?;
+    Ok(out)
+}
+
+/// Serialize `value` as canonical JSON into `wtr`.
+pub fn to_writer<W: io::Write, T: Serialize + ?Sized>(
+    mut wtr: W,
+    value: &T,
+) -> serde_json::Result<()> {
+    write_value(&mut wtr, &serde_json::to_value(value)?)
+}
+
+/// Serialize `value` as compact JSON into `wtr` with the members of every
+/// object sorted by name.
+///
+/// The record is serialized as usual and the members of its objects are
+/// then reordered, so numbers of any type and strings are written exactly
+/// as serde_json writes them.
+pub(crate) fn to_writer_sorted<W: io::Write, T: Serialize + ?Sized>(
+    mut wtr: W,
+    value: &T,
+) -> serde_json::Result<()> {
+    let json = serde_json::to_vec(value)?;
+    let mut sorted = Vec::with_capacity(json.len());
+    sort_members(&json, &mut 0, &mut sorted)?;
+    write_bytes(&mut wtr, &sorted)
+}
+
+/// Copy the JSON value at `pos` in `json` to `out` with the members of
+/// every object sorted by name, and advance `pos` past it.
+fn sort_members(json: &[u8], pos: &mut usize, out: &mut Vec<u8>) -> serde_json::Result<()> {
+    skip_whitespace(json, pos);
+    match json.get(*pos) {
+        Some(b'{') => {
+            *pos += 1;
+            let mut members = Vec::new();
+            skip_whitespace(json, pos);
+            if json.get(*pos) == Some(&b'}') {
+                *pos += 1;
+            } else {
+                loop {
+                    skip_whitespace(json, pos);
+                    let start = *pos;
+                    skip_string(json, pos)?;
+                    let name = &json[start..*pos];
+                    skip_whitespace(json, pos);
+                    expect(json, pos, b':')?;
+                    let mut member = name.to_vec();
+                    member.push(b':');
+                    sort_members(json, pos, &mut member)?;
+                    members.push((member_name(name)?, member));
+                    skip_whitespace(json, pos);
+                    if expect(json, pos, b',').is_err() {
+                        expect(json, pos, b'}')?;
+                        break;
+                    }
+                }
+            }
+            members.sort_by(|(a, _), (b, _)| a.cmp(b));
+            out.push(b'{');
+            for (i, (_, member)) in members.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                out.extend_from_slice(member);
+            }
+            out.push(b'}');
+        }
+        Some(b'[') => {
+            *pos += 1;
+            out.push(b'[');
+            skip_whitespace(json, pos);
+            if json.get(*pos) == Some(&b']') {
+                *pos += 1;
+            } else {
+                loop {
+                    sort_members(json, pos, out)?;
+                    skip_whitespace(json, pos);
+                    if expect(json, pos, b',').is_err() {
+                        expect(json, pos, b']')?;
+                        break;
+                    }
+                    out.push(b',');
+                }
+            }
+            out.push(b']');
+        }
+        Some(b'"') => {
+            let start = *pos;
+            skip_string(json, pos)?;
+            out.extend_from_slice(&json[start..*pos]);
+        }
+        _ => {
+            // A number or literal.
+            let start = *pos;
+            while json
+                .get(*pos)
+                .map_or(false, |&b| !is_whitespace(b) && !b",]}".contains(&b))
+            {
+                *pos += 1;
+            }
+            if *pos == start {
+                return Err(malformed());
+            }
+            out.extend_from_slice(&json[start..*pos]);
+        }
+    }
+    Ok(())
+}
+
+/// The name of an object member from its quoted JSON string.
+fn member_name(name: &[u8]) -> serde_json::Result<Cow<'_, str>> {
+    if name.contains(&b'\\') {
+        return serde_json::from_slice(name).map(Cow::Owned);
+    }
+    std::str::from_utf8(&name[1..name.len() - 1])
+        .map(Cow::Borrowed)
+        .map_err(serde_json::Error::custom)
+}
+
+fn skip_whitespace(json: &[u8], pos: &mut usize) {
+    while json.get(*pos).map_or(false, |&b| is_whitespace(b)) {
+        *pos += 1;
+    }
+}
+
+/// Advance `pos` past the JSON string that starts at it.
+fn skip_string(json: &[u8], pos: &mut usize) -> serde_json::Result<()> {
+    expect(json, pos, b'"')?;
+    loop {
+        match json.get(*pos) {
+            Some(b'"') => {
+                *pos += 1;
+                return Ok(());
+            }
+            Some(b'\\') => *pos += 2,
+            Some(_) => *pos += 1,
+            None => return Err(malformed()),
+        }
+    }
+}
+
+fn expect(json: &[u8], pos: &mut usize, b: u8) -> serde_json::Result<()> {
+    if json.get(*pos) != Some(&b) {
+        return Err(malformed());
+    }
+    *pos += 1;
+    Ok(())
+}
+
+fn malformed() -> serde_json::Error {
+    serde_json::Error::custom("serialized record is not valid JSON")
+}
+
+/// Write `value` as canonical JSON.
+fn write_value<W: io::Write>(wtr: &mut W, value: &Value) -> serde_json::Result<()> {
+    match value {
+        // serde_json already escapes strings as JCS requires.
+        Value::Null | Value::Bool(_) | Value::String(_) => serde_json::to_writer(&mut *wtr, value),
+        Value::Number(number) => {
+            let number = number
+                .as_f64()
+                .filter(|number| number.is_finite())
+                .ok_or_else(|| serde_json::Error::custom("number out of range"))?;
+            write_bytes(wtr, format_number(number).as_bytes())
+        }
+        Value::Array(values) => {
+            write_bytes(wtr, b"[")?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    write_bytes(wtr, b",")?;
+                }
+                write_value(wtr, value)?;
+            }
+            write_bytes(wtr, b"]")
+        }
+        Value::Object(map) => {
+            let mut members = map.iter().collect::<Vec<_>>();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            write_bytes(wtr, b"{")?;
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    write_bytes(wtr, b",")?;
+                }
+                serde_json::to_writer(&mut *wtr, name)?;
+                write_bytes(wtr, b":")?;
+                write_value(wtr, value)?;
+            }
+            write_bytes(wtr, b"}")
+        }
+    }
+}
+
+fn write_bytes<W: io::Write>(wtr: &mut W, bytes: &[u8]) -> serde_json::Result<()> {
+    wtr.write_all(bytes).map_err(serde_json::Error::io)
+}
+
+/// Format a finite number like ECMAScript's `Number.prototype.toString`.
+fn format_number(number: f64) -> String {
+    if number == 0.0 {
+        // This includes negative zero.
+        return "0".to_string();
+    }
+    // Rust writes the shortest digits that round-trip, as `d.ddde-x`.
+    let exp = format!("{:e}", number.abs());
+    let (mantissa, exponent) = exp.split_once('e').unwrap();
+    let digits = even_tie(number.abs(), mantissa.replace('.', ""));
+    let k = digits.len() as i32;
+    // The position of the decimal point relative to the digits.
+    let n = exponent.parse::<i32>().unwrap() + 1;
+
+    let mut out = String::with_capacity(32);
+    if number < 0.0 {
+        out.push('-');
+    }
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-n as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push('e');
+        if n > 0 {
+            out.push('+');
+        }
+        out.push_str(&(n - 1).to_string());
+    }
+    out
+}
+
+/// Return the shortest digits of `number`, choosing the even candidate when
+/// `number` lies exactly halfway between two, as ECMAScript does.
+///
+/// Rust rounds such ties up: it writes 1424953923781206.25 as
+/// `1424953923781206.3` rather than `1424953923781206.2`.
+fn even_tie(number: f64, digits: String) -> String {
+    let k = digits.len();
+    // A tie needs an exact value with one more significant digit, a 5.
+    let rounded = format!("{:.*e}", k, number);
+    if !rounded.split_once('e').unwrap().0.ends_with('5') {
+        return digits;
+    }
+    // Every double has at most 767 significant digits.
+    let exact = format!("{:.800e}", number);
+    let exact = exact.split_once('e').unwrap().0.replace('.', "");
+    let exact = exact.trim_end_matches('0');
+    match exact.as_bytes() {
+        [truncated @ .., b'5'] if truncated.len() == k && truncated[k - 1] % 2 == 0 => {
+            exact[..k].to_string()
+        }
+        _ => digits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+
+    use crate::canonical::{format_number, to_vec};
+    use serde_json::json;
+
+    #[test]
+    fn formats_numbers_like_ecmascript() {
+        // The number test vectors from RFC 8785, Appendix B.
+        let vectors = [
+            (0x0000000000000000, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ];
+        for (bits, expected) in vectors {
+            assert_eq!(format_number(f64::from_bits(bits)), expected, "{:x}", bits);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::excessive_precision)]
+    fn canonicalizes_the_rfc_examples() -> Result<(), Box<dyn Error>> {
+        // RFC 8785, Section 3.2.2. This is built with `json!` since Rust
+        // parses float literals exactly, unlike serde_json by default.
+        let value = json!({
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u{20ac}$\u{f}\nA'\u{42}\"\\\\\"/",
+            "literals": [null, true, false]
+        });
+        assert_eq!(
+            String::from_utf8(to_vec(&value)?)?,
+            concat!(
+                r#"{"literals":[null,true,false],"#,
+                r#""numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"#,
+                r#""string":"€$\u000f\nA'B\"\\\\\"/"}"#,
+            )
+        );
+
+        // RFC 8785, Section 3.2.3.
+        let input = r#"{
+            "\u20ac": "Euro Sign",
+            "\r": "Carriage Return",
+            "\ufb33": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\ud83d\ude00": "Emoji: Grinning Face",
+            "\u0080": "Control",
+            "\u00f6": "Latin Small Letter O With Diaeresis"
+        }"#;
+        let value: serde_json::Value = serde_json::from_str(input)?;
+        assert_eq!(
+            String::from_utf8(to_vec(&value)?)?,
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\
+             \"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\
+             \"\u{1f600}\":\"Emoji: Grinning Face\",\
+             \"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn canonicalizes_the_reference_test_data() -> Result<(), Box<dyn Error>> {
+        // The input and output files of the JCS reference implementations.
+        let vectors = [
+            (
+                "arrays",
+                r#"[56, {"d": true, "10": null, "1": [ ]}]"#,
+                r#"[56,{"1":[],"10":null,"d":true}]"#,
+            ),
+            (
+                "french",
+                r#"{
+                    "peach": "This sorting order",
+                    "p\u00e9ch\u00e9": "is wrong according to French",
+                    "p\u00eache": "but canonicalization MUST",
+                    "sin": "ignore locale"
+                }"#,
+                "{\"peach\":\"This sorting order\",\
+                 \"p\u{e9}ch\u{e9}\":\"is wrong according to French\",\
+                 \"p\u{ea}che\":\"but canonicalization MUST\",\
+                 \"sin\":\"ignore locale\"}",
+            ),
+            (
+                "structures",
+                r#"{
+                    "1": {"f": {"f": "hi", "F": 5}, "\n": 56.0},
+                    "10": { },
+                    "": "empty",
+                    "a": { },
+                    "111": [ {"e": "yes", "E": "no" } ],
+                    "A": { }
+                }"#,
+                r#"{"":"empty","1":{"\n":56,"f":{"F":5,"f":"hi"}},"10":{},"111":[{"E":"no","e":"yes"}],"A":{},"a":{}}"#,
+            ),
+            (
+                "unicode",
+                r#"{"Unnormalized Unicode": "A\u030a"}"#,
+                "{\"Unnormalized Unicode\":\"A\u{30a}\"}",
+            ),
+            (
+                // A surrogate pair sorts before BMP characters above U+E000
+                // in UTF-16, unlike in UTF-8 or by code point.
+                "weird",
+                r#"{
+                    "\u20ac": "Euro Sign",
+                    "\r": "Carriage Return",
+                    "\u000a": "Newline",
+                    "1": "One",
+                    "\u0080": "Control\u007f",
+                    "\ud83d\ude02": "Smiley",
+                    "\u00f6": "Latin Small Letter O With Diaeresis",
+                    "\ufb33": "Hebrew Letter Dalet With Dagesh",
+                    "</script>": "Browser Challenge"
+                }"#,
+                "{\"\\n\":\"Newline\",\"\\r\":\"Carriage Return\",\"1\":\"One\",\
+                 \"</script>\":\"Browser Challenge\",\"\u{80}\":\"Control\u{7f}\",\
+                 \"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\
+                 \"\u{1f602}\":\"Smiley\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}",
+            ),
+        ];
+        for (name, input, expected) in vectors {
+            let value: serde_json::Value = serde_json::from_str(input)?;
+            assert_eq!(String::from_utf8(to_vec(&value)?)?, expected, "{}", name);
+        }
+
+        let value = json!({"\u{ffff}": 4, "\u{e000}": 3, "\u{10000}": 2, "\u{d7ff}": 1});
+        assert_eq!(
+            String::from_utf8(to_vec(&value)?)?,
+            "{\"\u{d7ff}\":1,\"\u{10000}\":2,\"\u{e000}\":3,\"\u{ffff}\":4}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! [`tokio_util::codec`] support for JSON Lines and JSON array framing.
+//!
+//! [`JsonLinesCodec`] and [`JsonArrayCodec`] decode with the same
+//! [`RecordDecoder`] used by the rest of the crate, so they can be plugged
+//! straight into `FramedRead` and `FramedWrite` over TCP or Unix sockets.
+use crate::decoder::RecordDecoder;
+use crate::error::{Error, Result};
+use bytes::{BufMut, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A codec for streams of JSON Lines records.
+///
+/// Each encoded record is followed by a newline, so the receiving side sees
+/// every record as complete as soon as it arrives.
+#[derive(Debug)]
+pub struct JsonLinesCodec<T> {
+    decoder: RecordDecoder,
+    _priv: PhantomData<fn(T) -> T>,
+}
+
+impl<T> Default for JsonLinesCodec<T> {
+    fn default() -> Self {
+        JsonLinesCodec {
+            decoder: RecordDecoder::json_lines(),
+            _priv: PhantomData,
+        }
+    }
+}
+
+impl<T> JsonLinesCodec<T> {
+    /// Create a new JSON Lines codec.
+    pub fn new() -> JsonLinesCodec<T> {
+        JsonLinesCodec::default()
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for JsonLinesCodec<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        decode_next(&mut self.decoder, src)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        self.decoder.end_input();
+        decode_next(&mut self.decoder, src)
+    }
+}
+
+impl<T: Serialize> Encoder<T> for JsonLinesCodec<T> {
+    type Error = Error;
+
+    fn encode(&mut self, record: T, dst: &mut BytesMut) -> Result<()> {
+        serde_json::to_writer(dst.writer(), &record)?;
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+/// Marks the end of the array written by a [`JsonArrayCodec`].
+///
+/// Sending `EndOfArray` writes the closing `]`. If no record was sent
+/// before, it writes an empty array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndOfArray;
+
+/// A codec for a stream holding a single JSON array.
+///
+/// When decoding, no records follow the closing `]` and anything but
+/// whitespace after it is an error. The end of the input right after a
+/// complete record also ends the records cleanly. When encoding, the opening
+/// `[` is written before the first record and the closing `]` is written by
+/// sending [`EndOfArray`].
+///
+/// # Example
+///
+/// ```
+/// use std::error::Error;
+///
+/// use futures_util::{SinkExt, StreamExt};
+/// use json_arrays::codec::{EndOfArray, JsonArrayCodec};
+/// use tokio_util::codec::{FramedRead, FramedWrite};
+///
+/// # fn main() { example().unwrap(); }
+/// # #[tokio::main(flavor = "current_thread")]
+/// async fn example() -> Result<(), Box<dyn Error>> {
+///     let mut wtr = FramedWrite::new(vec![], JsonArrayCodec::<u32>::new());
+///     wtr.send(1).await?;
+///     wtr.send(2).await?;
+///     wtr.send(EndOfArray).await?;
+///     assert_eq!(wtr.get_ref(), b"[1,2]");
+///
+///     let data = wtr.into_inner();
+///     let mut rdr = FramedRead::new(&data[..], JsonArrayCodec::<u32>::new());
+///     assert_eq!(rdr.next().await.transpose()?, Some(1));
+///     assert_eq!(rdr.next().await.transpose()?, Some(2));
+///     assert_eq!(rdr.next().await.transpose()?, None);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct JsonArrayCodec<T> {
+    decoder: RecordDecoder,
+    encoded: EncodeState,
+    _priv: PhantomData<fn(T) -> T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EncodeState {
+    Start,
+    Elements,
+    End,
+}
+
+impl<T> Default for JsonArrayCodec<T> {
+    fn default() -> Self {
+        JsonArrayCodec {
+            decoder: RecordDecoder::array(),
+            encoded: EncodeState::Start,
+            _priv: PhantomData,
+        }
+    }
+}
+
+impl<T> JsonArrayCodec<T> {
+    /// Create a new JSON array codec.
+    pub fn new() -> JsonArrayCodec<T> {
+        JsonArrayCodec::default()
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for JsonArrayCodec<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        decode_next(&mut self.decoder, src)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>> {
+        self.decoder.end_input();
+        decode_next(&mut self.decoder, src)
+    }
+}
+
+impl<T: Serialize> Encoder<T> for JsonArrayCodec<T> {
+    type Error = Error;
+
+    fn encode(&mut self, record: T, dst: &mut BytesMut) -> Result<()> {
+        match self.encoded {
+            EncodeState::Start => dst.put_u8(b'['),
+            EncodeState::Elements => dst.put_u8(b','),
+            EncodeState::End => return Err(closed_array()),
+        }
+        self.encoded = EncodeState::Elements;
+        serde_json::to_writer(dst.writer(), &record)?;
+        Ok(())
+    }
+}
+
+impl<T> Encoder<EndOfArray> for JsonArrayCodec<T> {
+    type Error = Error;
+
+    fn encode(&mut self, _: EndOfArray, dst: &mut BytesMut) -> Result<()> {
+        match self.encoded {
+            EncodeState::Start => dst.put_slice(b"[]"),
+            EncodeState::Elements => dst.put_u8(b']'),
+            EncodeState::End => return Err(closed_array()),
+        }
+        self.encoded = EncodeState::End;
+        Ok(())
+    }
+}
+
+fn decode_next<T: DeserializeOwned>(
+    decoder: &mut RecordDecoder,
+    src: &mut BytesMut,
+) -> Result<Option<T>> {
+    if !src.is_empty() {
+        decoder.push(src);
+        src.clear();
+    }
+    match decoder.next_record()? {
+        Some(obj) => Ok(Some(obj.deserialize()?)),
+        None => Ok(None),
+    }
+}
+
+fn closed_array() -> Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "the array has already been closed",
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+
+    use crate::codec::{EndOfArray, JsonArrayCodec, JsonLinesCodec};
+    use futures_util::{SinkExt, StreamExt};
+    use serde::{Deserialize, Serialize};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Row {
+        id: u32,
+        name: String,
+    }
+
+    fn rows() -> Vec<Row> {
+        (0..100)
+            .map(|id| Row {
+                id,
+                name: format!("row ]{}[", id),
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn json_lines_over_socket_pair() -> Result<(), Box<dyn Error>> {
+        let (a, b) = tokio::net::UnixStream::pair()?;
+        let writer = tokio::spawn(async move {
+            let mut wtr = FramedWrite::new(a, JsonLinesCodec::<Row>::new());
+            for row in rows() {
+                wtr.send(row).await.unwrap();
+            }
+        });
+        let data = FramedRead::new(b, JsonLinesCodec::<Row>::new())
+            .map(|row| row.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        writer.await?;
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn array_over_pipe() -> Result<(), Box<dyn Error>> {
+        let (a, b) = tokio::io::duplex(32);
+        let writer = tokio::spawn(async move {
+            let mut wtr = FramedWrite::new(a, JsonArrayCodec::<Row>::new());
+            for row in rows() {
+                wtr.send(row).await.unwrap();
+            }
+            wtr.send(EndOfArray).await.unwrap();
+            SinkExt::<EndOfArray>::close(&mut wtr).await.unwrap();
+        });
+        let data = FramedRead::new(b, JsonArrayCodec::<Row>::new())
+            .map(|row| row.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        writer.await?;
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn decodes_end_of_array() -> Result<(), Box<dyn Error>> {
+        for input in ["[1,2]", "[1,2]\n", " [ 1 , 2 ] "] {
+            let data = FramedRead::new(input.as_bytes(), JsonArrayCodec::<u32>::new())
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(data, vec![1, 2]);
+        }
+        let mut rdr = FramedRead::new(&b"[1,2] 3"[..], JsonArrayCodec::<u32>::new());
+        assert_eq!(rdr.next().await.transpose()?, Some(1));
+        assert_eq!(rdr.next().await.transpose()?, Some(2));
+        assert!(rdr.next().await.unwrap().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encodes_empty_array() -> Result<(), Box<dyn Error>> {
+        let mut wtr = FramedWrite::new(vec![], JsonArrayCodec::<u32>::new());
+        wtr.send(EndOfArray).await?;
+        assert_eq!(wtr.get_ref(), b"[]");
+        assert!(wtr.send(1).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,736 @@
+//! A sans-IO decoder that splits JSON arrays and JSON Lines into records.
+//!
+//! [`RecordDecoder`] does no IO of its own: it is fed byte chunks of any
+//! size, split anywhere, and hands back the records that are complete so
+//! far. This makes it usable from blocking, asynchronous and callback-based
+//! front-ends alike, for example with message-queue payloads that arrive in
+//! arbitrary pieces.
+use crate::bytes_object::BytesObject;
+use crate::error::{Error, Limit, Result};
+use crate::reader::TrailingData;
+use std::ops::Range;
+
+/// The UTF-8 encoding of U+FEFF, the byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the opening `[` of an array.
+    ArrayStart,
+    /// After `[`, before the first element or `]`.
+    FirstElement,
+    /// After `,`, before the next element.
+    NextElement,
+    /// After an element, before `,` or `]`.
+    AfterElement,
+    /// After the closing `]`.
+    ArrayEnd,
+    /// Between JSON Lines records.
+    Line,
+    /// Inside a record.
+    Record,
+    /// Skipping malformed input after an error.
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Container,
+    String,
+    Scalar,
+}
+
+/// Splits a byte stream into records.
+///
+/// The decoder only tracks strings and bracket nesting to find where each
+/// record ends; validating the JSON inside a record is left to Serde when
+/// the record is deserialized with [`BytesObject::deserialize`].
+///
+/// # Example
+///
+/// ```
+/// use std::error::Error;
+///
+/// use json_arrays::decoder::RecordDecoder;
+///
+/// # fn main() { example().unwrap(); }
+/// fn example() -> Result<(), Box<dyn Error>> {
+///     let mut decoder = RecordDecoder::array();
+///     let records = decoder.feed(b"[{\"id\":1},{\"i")?;
+///     assert_eq!(records.len(), 1);
+///
+///     let records = decoder.feed(b"d\":2}]")?;
+///     assert_eq!(records.len(), 1);
+///     let record: serde_json::Value = records[0].deserialize()?;
+///     assert_eq!(record["id"], 2);
+///
+///     assert!(decoder.finish()?.is_empty());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordDecoder {
+    is_json_lines: bool,
+    eof: bool,
+    buf: Vec<u8>,
+    /// The length of the input being scanned.
+    len: usize,
+    /// The offset of `buf[0]` in the input.
+    base: u64,
+    pos: usize,
+    start: usize,
+    state: State,
+    kind: ValueKind,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Whether JSON Lines records always end at a newline.
+    line_bounded: bool,
+    max_record_bytes: Option<u64>,
+    max_depth: Option<usize>,
+    trailing_data: TrailingData,
+    skip_bom: bool,
+    require_array_end: bool,
+    recover_truncated: bool,
+    /// The offset just past the last complete element or opening `[`.
+    last_end: Option<u64>,
+}
+
+impl RecordDecoder {
+    /// Create a decoder for a single top-level JSON array.
+    pub fn array() -> RecordDecoder {
+        RecordDecoder::new(false)
+    }
+
+    /// Create a decoder for JSON Lines.
+    pub fn json_lines() -> RecordDecoder {
+        RecordDecoder::new(true)
+    }
+
+    pub(crate) fn new(is_json_lines: bool) -> RecordDecoder {
+        RecordDecoder {
+            is_json_lines,
+            eof: false,
+            buf: Vec::new(),
+            len: 0,
+            base: 0,
+            pos: 0,
+            start: 0,
+            state: if is_json_lines {
+                State::Line
+            } else {
+                State::ArrayStart
+            },
+            kind: ValueKind::Scalar,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            line_bounded: false,
+            max_record_bytes: None,
+            max_depth: None,
+            trailing_data: TrailingData::Reject,
+            skip_bom: false,
+            require_array_end: false,
+            recover_truncated: false,
+            last_end: None,
+        }
+    }
+
+    /// End an array quietly after its last complete element if the input
+    /// ends before the closing `]`.
+    pub(crate) fn set_recover_truncated(&mut self, yes: bool) {
+        self.recover_truncated = yes && !self.is_json_lines;
+    }
+
+    /// The offset at which the array should be cut to drop a truncated
+    /// tail, once the input has ended before its closing `]`.
+    ///
+    /// This is just past the last complete element, or just past the `[`
+    /// if there is none, or 0 if the input is empty.
+    pub(crate) fn truncated_at(&self) -> Option<u64> {
+        if self.is_json_lines || !self.eof || self.state == State::ArrayEnd {
+            return None;
+        }
+        Some(self.last_end.unwrap_or(0))
+    }
+
+    /// Set what may follow the closing `]` of an array, whether a byte order
+    /// mark at the start of the input is skipped, and whether the input
+    /// ending before the closing `]` is an error.
+    pub(crate) fn set_document_checks(
+        &mut self,
+        trailing_data: TrailingData,
+        skip_bom: bool,
+        require_array_end: bool,
+    ) {
+        self.trailing_data = trailing_data;
+        self.skip_bom = skip_bom;
+        self.require_array_end = require_array_end;
+    }
+
+    /// Fail with [`Error::LimitExceeded`] as soon as a record grows beyond
+    /// `max_record_bytes` or nests deeper than `max_depth`, so that an
+    /// oversized record is never buffered in full.
+    pub(crate) fn set_limits(&mut self, max_record_bytes: Option<u64>, max_depth: Option<usize>) {
+        self.max_record_bytes = max_record_bytes;
+        self.max_depth = max_depth;
+    }
+
+    /// End JSON Lines records at the first newline, even inside an unclosed
+    /// container, so that a malformed line cannot swallow the lines after it.
+    pub(crate) fn set_line_bounded(&mut self, yes: bool) {
+        self.line_bounded = yes && self.is_json_lines;
+    }
+
+    /// Count positions from `offset`, for input that starts in the middle
+    /// of a file. This must be called before any input is pushed.
+    pub(crate) fn set_offset(&mut self, offset: u64) {
+        self.base = offset;
+    }
+
+    /// Whether the decoder reads JSON Lines.
+    pub fn is_json_lines(&self) -> bool {
+        self.is_json_lines
+    }
+
+    /// Feed a chunk of input and return the records it completes.
+    ///
+    /// A record that is still incomplete at the end of `chunk` is buffered
+    /// until a later chunk completes it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<BytesObject>> {
+        self.push(chunk);
+        self.drain()
+    }
+
+    /// Signal the end of the input and return the remaining records.
+    ///
+    /// This returns an error if the input ends inside a record. A top-level
+    /// number or literal at the very end of the input is only known to be
+    /// complete at this point.
+    pub fn finish(&mut self) -> Result<Vec<BytesObject>> {
+        self.end_input();
+        self.drain()
+    }
+
+    fn drain(&mut self) -> Result<Vec<BytesObject>> {
+        let mut records = Vec::new();
+        while let Some(obj) = self.next_record()? {
+            records.push(obj);
+        }
+        Ok(records)
+    }
+
+    /// Append a chunk of input without decoding it.
+    ///
+    /// Use [`next_record`](RecordDecoder::next_record) to take the decoded
+    /// records out one at a time.
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.is_finished() {
+            return;
+        }
+        let keep = if matches!(self.state, State::Record | State::Skip) {
+            self.start
+        } else {
+            self.pos
+        };
+        if keep > 0 {
+            self.buf.drain(..keep);
+            self.base += keep as u64;
+            self.pos -= keep;
+            self.start = self.start.saturating_sub(keep);
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Whether the decoder has reached the end of the records before the end
+    /// of the input.
+    ///
+    /// This is the case after the closing `]` of an array when trailing data
+    /// is ignored. Nothing after it is decoded, so the rest of the input
+    /// need not be pushed.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::ArrayEnd && self.trailing_data == TrailingData::Ignore
+    }
+
+    /// Signal that no more input will be pushed.
+    pub fn end_input(&mut self) {
+        self.eof = true;
+    }
+
+    /// Take the next complete record out of the buffered input.
+    ///
+    /// Returns `Ok(None)` if more input is needed, or, after
+    /// [`end_input`](RecordDecoder::end_input), if there are no more records.
+    pub fn next_record(&mut self) -> Result<Option<BytesObject>> {
+        let buf = std::mem::take(&mut self.buf);
+        let span = self.next_span(&buf);
+        self.buf = buf;
+        Ok(span?.map(|span| self.object(span)))
+    }
+
+    /// Take the next record straight out of `input`, which holds the whole
+    /// input, without copying it.
+    ///
+    /// Returns the record with its position. Nothing may have been pushed
+    /// to a decoder used this way.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn next_record_in<'a>(
+        &mut self,
+        input: &'a [u8],
+    ) -> Result<Option<(u64, &'a [u8])>> {
+        self.eof = true;
+        let span = self.next_span(input)?;
+        Ok(span.map(|span| (self.base + span.start as u64, &input[span])))
+    }
+
+    /// Skip the malformed input at the current position of `input` after an
+    /// error, like [`skip_invalid`](RecordDecoder::skip_invalid) does for
+    /// pushed input.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn skip_invalid_in<'a>(&mut self, input: &'a [u8]) -> Option<(u64, &'a [u8])> {
+        self.eof = true;
+        let span = self.skip_span(input)?;
+        Some((self.base + span.start as u64, &input[span]))
+    }
+
+    /// Find the next record in `buf`, the buffered input.
+    fn next_span(&mut self, buf: &[u8]) -> Result<Option<Range<usize>>> {
+        self.len = buf.len();
+        if self.is_finished() {
+            return Ok(None);
+        }
+        if self.base + self.pos as u64 == 0 && !buf.is_empty() {
+            let n = buf.len().min(BOM.len());
+            if buf[..n] == BOM[..n] {
+                if n < BOM.len() && !self.eof {
+                    return Ok(None);
+                }
+                if n == BOM.len() && self.skip_bom {
+                    self.pos = n;
+                } else if n == BOM.len() {
+                    return Err(self.syntax_error("unexpected UTF-8 byte order mark"));
+                }
+            }
+        }
+        while self.pos < buf.len() {
+            let b = buf[self.pos];
+            if self.state == State::Record {
+                let end = self.scan_record(b);
+                self.check_limits(end.unwrap_or(self.pos + 1))?;
+                if let Some(end) = end {
+                    return Ok(Some(self.emit(end)));
+                }
+                self.pos += 1;
+                continue;
+            }
+            if is_whitespace(b) {
+                self.pos += 1;
+                continue;
+            }
+            match (self.state, b) {
+                (State::ArrayStart, b'[') => {
+                    self.state = State::FirstElement;
+                    self.last_end = Some(self.base + self.pos as u64 + 1);
+                }
+                (State::ArrayStart, _) => return Err(self.syntax_error("expected `[`")),
+                (State::FirstElement, b']') | (State::AfterElement, b']') => {
+                    self.state = State::ArrayEnd;
+                    if self.is_finished() {
+                        self.pos += 1;
+                        return Ok(None);
+                    }
+                }
+                (State::AfterElement, b',') => self.state = State::NextElement,
+                (State::AfterElement, _) => return Err(self.syntax_error("expected `,` or `]`")),
+                (State::ArrayEnd, b'[') if self.trailing_data == TrailingData::Concatenated => {
+                    self.state = State::FirstElement
+                }
+                (State::ArrayEnd, _) => {
+                    return Err(self.syntax_error("trailing characters after array"))
+                }
+                (_, b',') | (_, b']') | (_, b'}') => {
+                    return Err(self.syntax_error("expected a value"))
+                }
+                _ => {
+                    self.begin_record(b);
+                    continue;
+                }
+            }
+            self.pos += 1;
+        }
+        if !self.eof {
+            return Ok(None);
+        }
+        if self.state == State::Record && self.recover_truncated {
+            // A number or literal at the very end cannot be told apart from
+            // a cut-off one, so it is dropped along with any other record.
+            self.pos = buf.len();
+            self.state = State::AfterElement;
+        }
+        if self.state != State::Record {
+            return match self.state {
+                _ if !self.require_array_end || self.is_json_lines => Ok(None),
+                _ if self.recover_truncated => Ok(None),
+                State::ArrayStart => Err(self.syntax_error("expected `[`")),
+                State::ArrayEnd => Ok(None),
+                _ => Err(self.syntax_error("unexpected end of input, expected `]`")),
+            };
+        }
+        if self.kind == ValueKind::Scalar {
+            return Ok(Some(self.emit(self.pos)));
+        }
+        Err(self.syntax_error("unexpected end of input inside a record"))
+    }
+
+    /// Whether decoding can continue with
+    /// [`skip_invalid`](RecordDecoder::skip_invalid) after the last error.
+    ///
+    /// Errors before the opening `[`, after the closing `]` or at the end
+    /// of the input outside of a record concern the whole document rather
+    /// than one record, so they cannot be skipped.
+    pub fn can_recover(&self) -> bool {
+        let at_end = self.eof && self.pos == self.len && self.state != State::Record;
+        !matches!(self.state, State::ArrayStart | State::ArrayEnd) && !at_end
+    }
+
+    /// Skip the malformed input at the current position after an error.
+    ///
+    /// For JSON Lines, decoding resumes at the next newline. For arrays, it
+    /// resumes at the next `,` or `]` outside of any string or nested value.
+    /// Returns the skipped bytes, with the offset at which they start, once
+    /// the resume point is found, or `None` if more input is needed, in
+    /// which case this should be called again after the next
+    /// [`push`](RecordDecoder::push).
+    pub fn skip_invalid(&mut self) -> Option<BytesObject> {
+        let buf = std::mem::take(&mut self.buf);
+        let span = self.skip_span(&buf);
+        self.buf = buf;
+        span.map(|span| self.object(span))
+    }
+
+    /// Skip the malformed input at the current position of `buf`, the
+    /// buffered input.
+    fn skip_span(&mut self, buf: &[u8]) -> Option<Range<usize>> {
+        self.len = buf.len();
+        match self.state {
+            State::Skip => {}
+            // The scan state of a record that the input ended in is kept, so
+            // its nesting is still known.
+            State::Record => self.state = State::Skip,
+            _ => {
+                self.state = State::Skip;
+                self.start = self.pos;
+                self.depth = 0;
+                self.in_string = false;
+                self.escaped = false;
+            }
+        }
+        while self.pos < buf.len() {
+            if self.skip_ends(buf[self.pos]) {
+                return Some(self.emit_skipped(buf));
+            }
+            self.pos += 1;
+        }
+        if self.eof {
+            return Some(self.emit_skipped(buf));
+        }
+        None
+    }
+
+    /// Advance the skip scan over `b`, returning whether decoding can resume
+    /// at `b`.
+    fn skip_ends(&mut self, b: u8) -> bool {
+        if self.is_json_lines {
+            return b == b'\n';
+        }
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if b == b'\\' {
+                self.escaped = true;
+            } else if b == b'"' {
+                self.in_string = false;
+            }
+            return false;
+        }
+        match b {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' if self.depth > 0 => self.depth -= 1,
+            b',' | b']' if self.depth == 0 => return true,
+            _ => {}
+        }
+        false
+    }
+
+    fn emit_skipped(&mut self, buf: &[u8]) -> Range<usize> {
+        let mut end = self.pos;
+        while end > self.start && is_whitespace(buf[end - 1]) {
+            end -= 1;
+        }
+        let span = self.start..end;
+        self.start = self.pos;
+        // The separator at `pos`, if any, is handled as after any record.
+        self.state = if self.is_json_lines {
+            State::Line
+        } else {
+            State::AfterElement
+        };
+        span
+    }
+
+    fn begin_record(&mut self, b: u8) {
+        self.state = State::Record;
+        self.start = self.pos;
+        self.kind = match b {
+            b'{' | b'[' => ValueKind::Container,
+            b'"' => ValueKind::String,
+            _ => ValueKind::Scalar,
+        };
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+    }
+
+    /// Advance the record scan over `b`, returning the end of the record if
+    /// `b` completes it.
+    fn scan_record(&mut self, b: u8) -> Option<usize> {
+        if self.line_bounded && b == b'\n' {
+            return Some(self.pos);
+        }
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if b == b'\\' {
+                self.escaped = true;
+            } else if b == b'"' {
+                self.in_string = false;
+                if self.kind == ValueKind::String {
+                    return Some(self.pos + 1);
+                }
+            }
+            return None;
+        }
+        match (self.kind, b) {
+            (ValueKind::Scalar, b',' | b']' | b'}') => Some(self.pos),
+            (ValueKind::Scalar, b) if is_whitespace(b) => Some(self.pos),
+            (ValueKind::Scalar, _) => None,
+            (_, b'"') => {
+                self.in_string = true;
+                None
+            }
+            (ValueKind::Container, b'{' | b'[') => {
+                self.depth += 1;
+                None
+            }
+            (ValueKind::Container, b'}' | b']') => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    Some(self.pos + 1)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Check the limits for the record scanned up to `end`.
+    fn check_limits(&self, end: usize) -> Result<()> {
+        let limit = match (self.max_record_bytes, self.max_depth) {
+            (Some(max), _) if (end - self.start) as u64 > max => Limit::RecordBytes(max),
+            (_, Some(max)) if self.depth > max => Limit::Depth(max),
+            _ => return Ok(()),
+        };
+        Err(Error::LimitExceeded {
+            limit,
+            position: self.base + self.start as u64,
+        })
+    }
+
+    fn emit(&mut self, end: usize) -> Range<usize> {
+        self.last_end = Some(self.base + end as u64);
+        let span = self.start..end;
+        self.pos = end;
+        self.start = end;
+        self.state = if self.is_json_lines {
+            State::Line
+        } else {
+            State::AfterElement
+        };
+        span
+    }
+
+    /// Copy the buffered input in `span` into a record.
+    fn object(&self, span: Range<usize>) -> BytesObject {
+        BytesObject::from_parts(
+            self.buf[span.clone()].to_vec(),
+            self.base + span.start as u64,
+        )
+    }
+
+    fn syntax_error(&self, msg: &'static str) -> Error {
+        Error::SyntaxError {
+            position: self.base + self.pos as u64,
+            msg,
+        }
+    }
+}
+
+/// Whether `b` is whitespace between JSON tokens.
+///
+/// Unlike `u8::is_ascii_whitespace`, this excludes form feed, which RFC 8259
+/// does not allow.
+pub(crate) fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::bytes_object::BytesObject;
+    use crate::decoder::RecordDecoder;
+    use crate::error::Error;
+
+    fn records(is_json_lines: bool, chunks: &[&str]) -> Result<Vec<String>, Error> {
+        let mut decoder = RecordDecoder::new(is_json_lines);
+        let mut records = Vec::new();
+        for chunk in chunks {
+            records.extend(decoder.feed(chunk.as_bytes())?);
+        }
+        records.extend(decoder.finish()?);
+        Ok(records
+            .into_iter()
+            .map(|obj| String::from_utf8(obj.into_bytes()).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn splits_array() -> Result<(), Error> {
+        let input = r#" [ {"a":"]},\"["}, [1,[2]] ,"x,y", 12 ,true,-0.5e3 ] "#;
+        let expected = vec![
+            r#"{"a":"]},\"["}"#,
+            "[1,[2]]",
+            r#""x,y""#,
+            "12",
+            "true",
+            "-0.5e3",
+        ];
+        assert_eq!(records(false, &[input])?, expected);
+        let chunks = input
+            .as_bytes()
+            .chunks(1)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records(false, &chunks)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn splits_json_lines() -> Result<(), Error> {
+        assert_eq!(
+            records(true, &["{\"a\":1}\n", "\n[2,", "3]\r\n4", "2\n\"s\""])?,
+            vec!["{\"a\":1}", "[2,3]", "42", "\"s\""]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn splits_anywhere() -> Result<(), Error> {
+        let input = "{\"a\":[1,\"\\\"}\"]}\n17\n\"x\"\n";
+        let expected = records(true, &[input])?;
+        assert_eq!(expected.len(), 3);
+        for i in 0..input.len() {
+            assert_eq!(records(true, &[&input[..i], &input[i..]])?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(matches!(
+            records(false, &["[1,,2]"]),
+            Err(Error::SyntaxError { position: 3, .. })
+        ));
+        assert!(records(false, &["{}"]).is_err());
+        assert!(records(false, &["[1] x"]).is_err());
+        // A form feed is not skipped, so deserializing the record fails.
+        assert_eq!(records(false, &["[1,\x0c2]"]).unwrap()[1], "\x0c2");
+        assert!(records(false, &["\x0c[]"]).is_err());
+        assert!(records(true, &["{\"a\":"]).is_err());
+    }
+
+    /// Decode `input` in one-byte chunks, skipping malformed input, and
+    /// return the records and skipped spans in order.
+    fn recover(is_json_lines: bool, input: &str) -> Vec<Result<String, String>> {
+        let mut decoder = RecordDecoder::new(is_json_lines);
+        decoder.set_line_bounded(true);
+        let mut out = Vec::new();
+        let mut skipping = false;
+        let to_string = |obj: BytesObject| String::from_utf8(obj.into_bytes()).unwrap();
+        for i in 0..=input.len() {
+            if i == input.len() {
+                decoder.end_input();
+            } else {
+                decoder.push(&input.as_bytes()[i..i + 1]);
+            }
+            loop {
+                if skipping {
+                    match decoder.skip_invalid() {
+                        Some(obj) => {
+                            out.push(Err(to_string(obj)));
+                            skipping = false;
+                        }
+                        None => break,
+                    }
+                }
+                match decoder.next_record() {
+                    Ok(Some(obj)) => out.push(Ok(to_string(obj))),
+                    Ok(None) => break,
+                    Err(err) => {
+                        assert!(decoder.can_recover(), "{} {:?}", err, out);
+                        skipping = true;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn skips_invalid_input() {
+        assert_eq!(
+            recover(false, r#"[1 "x,]" {"a":[2,]}, 3,, }4, 5 "#),
+            vec![
+                Ok("1".to_string()),
+                Err(r#""x,]" {"a":[2,]}"#.to_string()),
+                Ok("3".to_string()),
+                Err("".to_string()),
+                Err("}4".to_string()),
+                Ok("5".to_string()),
+            ]
+        );
+        assert_eq!(
+            recover(true, "1\n{\"a\":\n,2\n3\n{\"b\""),
+            vec![
+                Ok("1".to_string()),
+                Ok("{\"a\":".to_string()),
+                Err(",2".to_string()),
+                Ok("3".to_string()),
+                Err("{\"b\"".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_positions() -> Result<(), Error> {
+        let mut decoder = RecordDecoder::array();
+        decoder.push(b"[10, ");
+        assert_eq!(decoder.next_record()?.unwrap().position(), 1);
+        decoder.push(b"20]");
+        assert_eq!(decoder.next_record()?.unwrap().position(), 5);
+        decoder.end_input();
+        assert_eq!(decoder.next_record()?, None);
+        Ok(())
+    }
+}
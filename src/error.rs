@@ -1,3 +1,4 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::error::Error as StdError;
 use std::fmt::Display;
 use std::io;
@@ -8,12 +9,62 @@ pub type Result<T> = result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     IoError(io::Error),
+    JsonError(serde_json::Error),
+    /// The input is not a well-formed JSON array or JSON Lines stream.
+    SyntaxError {
+        position: u64,
+        msg: &'static str,
+    },
+    /// Two records share the same key in a key index.
+    DuplicateKey {
+        key: String,
+        position: u64,
+    },
+    /// A configured resource limit was exceeded.
+    LimitExceeded {
+        limit: Limit,
+        position: u64,
+    },
+}
+
+/// A resource limit of a reader or writer, with its configured maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The maximum size of a single record in bytes.
+    RecordBytes(u64),
+    /// The maximum nesting depth of arrays and objects within a record.
+    Depth(usize),
+    /// The maximum number of records.
+    Records(u64),
+    /// The maximum total size of the input or output in bytes.
+    TotalBytes(u64),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RecordBytes(max) => write!(f, "record size limit of {} bytes", max),
+            Self::Depth(max) => write!(f, "nesting depth limit of {}", max),
+            Self::Records(max) => write!(f, "record count limit of {}", max),
+            Self::TotalBytes(max) => write!(f, "total size limit of {} bytes", max),
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::IoError(_) => write!(f, "IO error"),
+            Self::JsonError(_) => write!(f, "JSON error"),
+            Self::SyntaxError { position, msg } => {
+                write!(f, "syntax error at byte {}: {}", position, msg)
+            }
+            Self::DuplicateKey { key, position } => {
+                write!(f, "duplicate key {} at byte {}", key, position)
+            }
+            Self::LimitExceeded { limit, position } => {
+                write!(f, "{} exceeded at byte {}", limit, position)
+            }
         }
     }
 }
@@ -22,12 +73,105 @@ impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             Self::IoError(err) => Some(err),
+            Self::JsonError(err) => Some(err),
+            Self::SyntaxError { .. } | Self::DuplicateKey { .. } | Self::LimitExceeded { .. } => {
+                None
+            }
         }
     }
 }
 
+/// A malformed record that a lenient reader skipped.
+///
+/// See [`ErrorPolicy`](crate::reader::ErrorPolicy).
+#[derive(Debug)]
+pub struct RecordError {
+    pub(crate) index: u64,
+    pub(crate) position: u64,
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) error: Error,
+}
+
+impl RecordError {
+    /// The index of the record in the input, counting both valid and
+    /// malformed records from zero.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// The byte offset of the start of the record in the input.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The raw bytes of the record.
+    ///
+    /// For a record that could not be delimited, these are the bytes that
+    /// were skipped to reach the next record.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The error that the record caused.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// Consume the report and return the error.
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "record {} at byte {}: {}",
+            self.index, self.position, self.error
+        )
+    }
+}
+
+impl StdError for RecordError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Serializes the report as a dead-letter record.
+///
+/// The record has the fields `index`, `position`, `error`, a description of
+/// the error, and `record`, the raw bytes as a string. Raw bytes that are not
+/// valid UTF-8 are written as an array of numbers in `record_bytes` instead,
+/// so the record can always be replayed exactly.
+impl Serialize for RecordError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RecordError", 4)?;
+        state.serialize_field("index", &self.index)?;
+        state.serialize_field("position", &self.position)?;
+        let description = match &self.error {
+            Error::IoError(err) => err.to_string(),
+            Error::JsonError(err) => err.to_string(),
+            err => err.to_string(),
+        };
+        state.serialize_field("error", &description)?;
+        match std::str::from_utf8(&self.bytes) {
+            Ok(text) => state.serialize_field("record", text)?,
+            Err(_) => state.serialize_field("record_bytes", &self.bytes)?,
+        }
+        state.end()
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
         Self::IoError(err)
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::JsonError(err)
+    }
+}
@@ -0,0 +1,271 @@
+//! Following a growing JSON Lines file, like `tail -f`.
+//!
+//! A [`Reader`](crate::Reader) built with
+//! [`ReaderBuilder::follow`](crate::ReaderBuilder::follow) does not stop at
+//! the end of its file. Once it has read everything, it checks whether the
+//! file was truncated, or whether the path now refers to a different file,
+//! as happens with log rotation. If so, it starts over with the file at the
+//! path; otherwise it waits for more data to be appended.
+use crate::decoder::RecordDecoder;
+use std::{
+    fs::{self, File},
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Identifies the file behind a path, to notice when it is replaced.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &fs::Metadata) -> FileId {}
+
+/// What a [`Reader`](crate::Reader) needs to follow the file at a path.
+#[derive(Debug)]
+pub(crate) struct Follow<R> {
+    path: PathBuf,
+    id: FileId,
+    /// The offset in the file up to which data has been read.
+    pos: u64,
+    poll_interval: Duration,
+    /// A decoder for reading the file from its start.
+    decoder: RecordDecoder,
+    /// Turns a reopened file into the reader's input.
+    from_file: fn(File) -> R,
+}
+
+impl Follow<File> {
+    /// Open the file at `path` to follow it, at its end if `from_end` is
+    /// set.
+    ///
+    /// `decoder` is used whenever the file is read from its start.
+    pub(crate) fn open(
+        path: &Path,
+        from_end: bool,
+        poll_interval: Duration,
+        decoder: RecordDecoder,
+    ) -> io::Result<(File, Follow<File>)> {
+        let mut file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+        let pos = if from_end {
+            file.seek(SeekFrom::End(0))?
+        } else {
+            0
+        };
+        let follow = Follow {
+            path: path.to_path_buf(),
+            id,
+            pos,
+            poll_interval,
+            decoder,
+            from_file: |file| file,
+        };
+        Ok((file, follow))
+    }
+}
+
+impl<R> Follow<R> {
+    /// The offset in the file up to which data has been read.
+    pub(crate) fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// How long to wait before looking for more data.
+    pub(crate) fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Record that `n` more bytes have been read.
+    pub(crate) fn advance(&mut self, n: usize) {
+        self.pos += n as u64;
+    }
+
+    /// Reopen the file if it was truncated or replaced since it was
+    /// opened.
+    ///
+    /// Returns the reopened file, to be read from its start with the
+    /// returned decoder, or `None` if nothing changed.
+    pub(crate) fn reopen_if_replaced(&mut self) -> io::Result<Option<(R, RecordDecoder)>> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // The file may be in the middle of being rotated.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if file_id(&metadata) == self.id && metadata.len() >= self.pos {
+            return Ok(None);
+        }
+        let file = File::open(&self.path)?;
+        self.id = file_id(&file.metadata()?);
+        self.pos = 0;
+        Ok(Some(((self.from_file)(file), self.decoder.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::fs::{self, File, OpenOptions};
+    use std::time::Duration;
+
+    use crate::reader::{ErrorPolicy, Reader, ReaderBuilder};
+    use crate::test_util::TempPath;
+
+    fn follow(path: &TempPath) -> Reader<File> {
+        ReaderBuilder::new()
+            .json_lines(true)
+            .follow(true)
+            .from_path(path)
+            .unwrap()
+    }
+
+    fn next(rdr: &mut Reader<File>) -> Option<String> {
+        let obj = rdr.try_read_object().unwrap()?;
+        Some(String::from_utf8(obj.into_bytes()).unwrap())
+    }
+
+    #[test]
+    fn waits_for_complete_lines() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("lines.jsonl");
+        path.append("1\n2\n");
+        let mut rdr = follow(&path);
+        assert_eq!(next(&mut rdr).as_deref(), Some("1"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("2"));
+        assert_eq!(next(&mut rdr), None);
+
+        path.append("3");
+        assert_eq!(next(&mut rdr), None);
+        path.append("4\n\n{\"a\":");
+        assert_eq!(next(&mut rdr).as_deref(), Some("34"));
+        assert_eq!(next(&mut rdr), None);
+        Ok(())
+    }
+
+    #[test]
+    fn follows_writer_output() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("writer.jsonl");
+        path.append("");
+        let mut rdr = follow(&path);
+        let mut wtr = crate::WriterBuilder::new()
+            .json_lines(true)
+            .from_writer(OpenOptions::new().append(true).open(&path)?);
+        wtr.serialize(serde_json::json!({"n": 1}))?;
+        assert_eq!(next(&mut rdr).as_deref(), Some("{\"n\":1}"));
+        assert_eq!(next(&mut rdr), None);
+        wtr.serialize(serde_json::json!({"n": 2}))?;
+        assert_eq!(next(&mut rdr).as_deref(), Some("{\"n\":2}"));
+        Ok(())
+    }
+
+    #[test]
+    fn waits_in_the_iterator() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("waiting.jsonl");
+        path.append("1\n");
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .follow(true)
+            .follow_interval(Duration::from_millis(1))
+            .from_path(&path)?;
+        let writer = {
+            let path = path.as_ref().to_path_buf();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| std::io::Write::write_all(&mut file, b"2\n"))
+                    .unwrap();
+            })
+        };
+        let records = rdr
+            .deserialize::<u32>()
+            .take(2)
+            .collect::<Result<Vec<_>, _>>()?;
+        writer.join().unwrap();
+        assert_eq!(records, [1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_the_error_policy() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("malformed.jsonl");
+        path.append("}\n1\n{\"a\":\n 2 3\n");
+        let mut rdr = follow(&path);
+        assert!(rdr.try_read_object().is_err());
+        assert_eq!(next(&mut rdr), None);
+
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .follow(true)
+            .error_policy(ErrorPolicy::Collect)
+            .max_records(4)
+            .from_path(&path)?;
+        assert_eq!(next(&mut rdr).as_deref(), Some("1"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("{\"a\":"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("2"));
+        assert_eq!(rdr.skipped(), 1);
+        assert!(rdr.try_read_object().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_positions_in_the_file() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("positions.jsonl");
+        path.append("1\n");
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .follow(true)
+            .follow_from_end(true)
+            .from_path(&path)?;
+        assert!(rdr.try_read_object()?.is_none());
+        path.append("22\n");
+        assert_eq!(rdr.try_read_object()?.unwrap().position(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn only_follows_json_lines() {
+        let path = TempPath::with_contents("array.json", "[]");
+        assert!(ReaderBuilder::new().follow(true).from_path(&path).is_err());
+    }
+
+    #[test]
+    fn restarts_after_truncation() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("truncated.jsonl");
+        path.append("111\n222\n");
+        let mut rdr = follow(&path);
+        assert_eq!(next(&mut rdr).as_deref(), Some("111"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("222"));
+        fs::write(&path, "3\n")?;
+        let obj = rdr.try_read_object()?.unwrap();
+        assert_eq!((obj.as_bytes(), obj.position()), (&b"3"[..], 0));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reopens_after_rotation() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::new("rotated.jsonl");
+        let rotated = TempPath::new("rotated.jsonl.1");
+        path.append("1\n");
+        let mut rdr = follow(&path);
+        assert_eq!(next(&mut rdr).as_deref(), Some("1"));
+        path.append("2\n");
+        fs::rename(&path, &rotated)?;
+        path.append("3\n4\n");
+        assert_eq!(next(&mut rdr).as_deref(), Some("2"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("3"));
+        assert_eq!(next(&mut rdr).as_deref(), Some("4"));
+        Ok(())
+    }
+}
@@ -0,0 +1,754 @@
+//! Sidecar record indexes for random access to uncompressed files.
+//!
+//! A [`RecordIndex`] holds the byte offset of every record in a JSON array
+//! or JSON Lines file. It can be built by scanning an existing file once, or
+//! for free while writing with an [`IndexedWriter`]. It is usually stored
+//! next to the data in a sidecar file (see [`sidecar_path`]), and an
+//! [`IndexedReader`] uses it to seek straight to any record.
+//!
+//! A [`KeyIndex`] maps the value found at a JSON Pointer in each record to
+//! the record's offset, and a [`KeyedReader`] uses it to look records up by
+//! key.
+use crate::decoder::RecordDecoder;
+use crate::error::{Error, Result};
+use crate::writer::{Writer, WriterBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const INDEX_MAGIC: &[u8; 4] = b"JAIX";
+const INDEX_VERSION: u8 = 1;
+const KEY_INDEX_MAGIC: &[u8; 4] = b"JAKX";
+const KEY_INDEX_VERSION: u8 = 1;
+
+/// The path of the sidecar index for the data file at `path`.
+///
+/// This appends `.idx` to the file name, so `rows.json` is indexed by
+/// `rows.json.idx`.
+pub fn sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    with_suffix(path, ".idx")
+}
+
+/// The path of the sidecar key index for the data file at `path`.
+///
+/// This appends `.keyidx` to the file name, so `rows.jsonl` is indexed by
+/// `rows.jsonl.keyidx`.
+pub fn key_sidecar_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    with_suffix(path, ".keyidx")
+}
+
+fn with_suffix<P: AsRef<Path>>(path: P, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_ref().as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The byte offsets of the records in a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordIndex {
+    is_json_lines: bool,
+    offsets: Vec<u64>,
+}
+
+impl RecordIndex {
+    /// Build an index by scanning a JSON array or JSON Lines stream once.
+    pub fn build<R: io::Read>(mut rdr: R, is_json_lines: bool) -> Result<RecordIndex> {
+        let mut decoder = RecordDecoder::new(is_json_lines);
+        let mut buf = vec![0; 64 * (1 << 10)];
+        let mut offsets = Vec::new();
+        loop {
+            while let Some(obj) = decoder.next_record()? {
+                offsets.push(obj.position());
+            }
+            let n = match rdr.read(&mut buf) {
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if n == 0 {
+                decoder.end_input();
+                while let Some(obj) = decoder.next_record()? {
+                    offsets.push(obj.position());
+                }
+                return Ok(RecordIndex {
+                    is_json_lines,
+                    offsets,
+                });
+            }
+            decoder.push(&buf[..n]);
+        }
+    }
+
+    /// Build an index by scanning the file at the given path once.
+    pub fn build_from_path<P: AsRef<Path>>(path: P, is_json_lines: bool) -> Result<RecordIndex> {
+        RecordIndex::build(File::open(path)?, is_json_lines)
+    }
+
+    /// The number of indexed records.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Whether the indexed records are in JSON Lines format.
+    pub fn is_json_lines(&self) -> bool {
+        self.is_json_lines
+    }
+
+    /// The byte offset of the first byte of record `n`.
+    pub fn get(&self, n: usize) -> Option<u64> {
+        self.offsets.get(n).copied()
+    }
+
+    /// Write the index in a compact binary format.
+    pub fn write_to<W: io::Write>(&self, mut wtr: W) -> Result<()> {
+        wtr.write_all(INDEX_MAGIC)?;
+        wtr.write_all(&[INDEX_VERSION, u8::from(self.is_json_lines)])?;
+        wtr.write_all(&(self.offsets.len() as u64).to_le_bytes())?;
+        for offset in &self.offsets {
+            wtr.write_all(&offset.to_le_bytes())?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Write the index to the given path. The file is truncated if it
+    /// already exists.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Read an index written by [`write_to`](RecordIndex::write_to).
+    pub fn read_from<R: io::Read>(mut rdr: R) -> Result<RecordIndex> {
+        let mut header = [0; 14];
+        rdr.read_exact(&mut header)?;
+        if &header[..4] != INDEX_MAGIC || header[4] != INDEX_VERSION {
+            return Err(invalid_data("not a record index"));
+        }
+        let len = u64::from_le_bytes(header[6..].try_into().unwrap());
+        let mut offsets = Vec::new();
+        let mut buf = [0; 8];
+        for _ in 0..len {
+            rdr.read_exact(&mut buf)?;
+            offsets.push(u64::from_le_bytes(buf));
+        }
+        Ok(RecordIndex {
+            is_json_lines: header[5] & 1 == 1,
+            offsets,
+        })
+    }
+
+    /// Read the index stored at the given path.
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> Result<RecordIndex> {
+        RecordIndex::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// A writer that records the byte offset of every record it writes.
+///
+/// Create one with
+/// [`WriterBuilder::from_indexed_writer`](crate::WriterBuilder::from_indexed_writer)
+/// and call [`finish`](IndexedWriter::finish) once all records are written
+/// to get the underlying writer and the record index back. If the writer is
+/// dropped without being finished, the output is closed on a best-effort
+/// basis and the index is lost.
+#[derive(Debug)]
+pub struct IndexedWriter<W: io::Write> {
+    wtr: Writer<CountingWriter<W>>,
+    index: RecordIndex,
+}
+
+impl<W: io::Write> IndexedWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> IndexedWriter<W> {
+        IndexedWriter {
+            wtr: builder.from_writer(CountingWriter {
+                inner: wtr,
+                count: 0,
+            }),
+            index: RecordIndex {
+                is_json_lines: builder.is_json_lines,
+                offsets: Vec::new(),
+            },
+        }
+    }
+
+    /// Serialize a single record using Serde and record its offset.
+    pub fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
+        let offsets = &mut self.index.offsets;
+        self.wtr.write_record(record, |wtr| offsets.push(wtr.count))
+    }
+
+    /// The index of the records written so far.
+    pub fn index(&self) -> &RecordIndex {
+        &self.index
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+
+    /// Close the JSON framing and return the underlying writer together
+    /// with the record index.
+    pub fn finish(self) -> Result<(W, RecordIndex)> {
+        let counting = self.wtr.into_inner()?;
+        Ok((counting.inner, self.index))
+    }
+}
+
+/// An `io::Write` that counts the bytes written through it.
+#[derive(Debug)]
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: io::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that uses a [`RecordIndex`] to read any record with one seek.
+#[derive(Debug)]
+pub struct IndexedReader<R> {
+    rdr: R,
+    index: RecordIndex,
+}
+
+impl IndexedReader<File> {
+    /// Open the file at the given path together with its sidecar index.
+    ///
+    /// The index is read from [`sidecar_path`]`(path)`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<IndexedReader<File>> {
+        let index = RecordIndex::read_from_path(sidecar_path(&path))?;
+        Ok(IndexedReader::new(File::open(path)?, index))
+    }
+}
+
+impl<R: io::Read + Seek> IndexedReader<R> {
+    /// Create a reader for `rdr` using an index of its records.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::index::IndexedReader;
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new().from_indexed_writer(vec![]);
+    ///     for i in 0..100 {
+    ///         wtr.serialize(i)?;
+    ///     }
+    ///     let (data, index) = wtr.finish()?;
+    ///
+    ///     let mut rdr = IndexedReader::new(Cursor::new(data), index);
+    ///     assert_eq!(rdr.len(), 100);
+    ///     assert_eq!(rdr.get::<u32>(42)?, Some(42));
+    ///     let page = rdr.range::<u32>(10..13)?.collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(page, vec![10, 11, 12]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new(rdr: R, index: RecordIndex) -> IndexedReader<R> {
+        IndexedReader { rdr, index }
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Whether there are no records.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The record index.
+    pub fn index(&self) -> &RecordIndex {
+        &self.index
+    }
+
+    /// Deserialize record `n`, or return `None` if there are not that many
+    /// records.
+    pub fn get<D: DeserializeOwned>(&mut self, n: usize) -> Result<Option<D>> {
+        if n >= self.len() {
+            return Ok(None);
+        }
+        self.range(n..n + 1)?.next().transpose()
+    }
+
+    /// Return an iterator over the deserialized records in `range`.
+    ///
+    /// The range is clamped to the number of records. The bytes holding the
+    /// records are read with a single seek when this is called.
+    pub fn range<D: DeserializeOwned>(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<DeserializeIndexedIter<D>> {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        let offsets = self.index.offsets[start..end].to_vec();
+        let mut buf = Vec::new();
+        if let Some(&first) = offsets.first() {
+            self.rdr.seek(SeekFrom::Start(first))?;
+            match self.index.get(end) {
+                Some(next) => {
+                    let mut take = (&mut self.rdr).take(next - first);
+                    take.read_to_end(&mut buf)?;
+                }
+                None => {
+                    self.rdr.read_to_end(&mut buf)?;
+                }
+            }
+        }
+        Ok(DeserializeIndexedIter {
+            buf,
+            base: offsets.first().copied().unwrap_or(0),
+            offsets: offsets.into_iter(),
+            _priv: PhantomData,
+        })
+    }
+
+    /// Return the underlying reader and the index.
+    pub fn into_inner(self) -> (R, RecordIndex) {
+        (self.rdr, self.index)
+    }
+}
+
+/// An owned iterator over records read by an [`IndexedReader`].
+#[derive(Debug)]
+pub struct DeserializeIndexedIter<D> {
+    buf: Vec<u8>,
+    base: u64,
+    offsets: std::vec::IntoIter<u64>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<D: DeserializeOwned> Iterator for DeserializeIndexedIter<D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        let offset = self.offsets.next()?;
+        let start = (offset - self.base) as usize;
+        let bytes = self.buf.get(start..).unwrap_or_default();
+        let mut iter = serde_json::Deserializer::from_slice(bytes).into_iter();
+        Some(match iter.next() {
+            Some(result) => result.map_err(Error::from),
+            None => Err(Error::SyntaxError {
+                position: offset,
+                msg: "expected a value",
+            }),
+        })
+    }
+}
+
+/// An index from the value at a JSON Pointer in each record to the record's
+/// byte offset.
+///
+/// Keys are compared by their compact JSON text, so the string `"7"` and the
+/// number `7` are different keys. Records without a value at the pointer are
+/// not indexed. The index remembers the size and modification time of the
+/// data it was built from, so a stale index can be detected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyIndex {
+    pointer: String,
+    data_len: u64,
+    data_modified: u64,
+    offsets: HashMap<String, u64>,
+}
+
+impl KeyIndex {
+    /// Build an index of the JSON Lines records in `rdr` keyed by the value
+    /// at `pointer`, such as `/id`.
+    ///
+    /// Returns [`Error::DuplicateKey`] if two records have the same key.
+    pub fn build<R: io::Read>(rdr: R, pointer: &str) -> Result<KeyIndex> {
+        let mut rdr = BufReader::new(rdr);
+        let mut decoder = RecordDecoder::json_lines();
+        let mut index = KeyIndex {
+            pointer: pointer.to_string(),
+            ..KeyIndex::default()
+        };
+        let mut buf = vec![0; 64 * (1 << 10)];
+        let mut eof = false;
+        loop {
+            while let Some(obj) = decoder.next_record()? {
+                let value: serde_json::Value = obj.deserialize()?;
+                let key = match value.pointer(pointer) {
+                    Some(key) => key.to_string(),
+                    None => continue,
+                };
+                if index.offsets.contains_key(&key) {
+                    return Err(Error::DuplicateKey {
+                        key,
+                        position: obj.position(),
+                    });
+                }
+                index.offsets.insert(key, obj.position());
+            }
+            if eof {
+                return Ok(index);
+            }
+            match rdr.read(&mut buf) {
+                Ok(0) => {
+                    eof = true;
+                    decoder.end_input();
+                }
+                Ok(n) => decoder.push(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Build an index of the JSON Lines file at the given path keyed by the
+    /// value at `pointer`.
+    pub fn build_from_path<P: AsRef<Path>>(path: P, pointer: &str) -> Result<KeyIndex> {
+        let file = File::open(path)?;
+        let (data_len, data_modified) = file_stamp(&file.metadata()?)?;
+        Ok(KeyIndex {
+            data_len,
+            data_modified,
+            ..KeyIndex::build(file, pointer)?
+        })
+    }
+
+    /// The JSON Pointer the index is keyed by.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// The number of indexed keys.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The byte offset of the record with the given key.
+    pub fn get<K: Serialize>(&self, key: K) -> Result<Option<u64>> {
+        let key = serde_json::to_string(&key)?;
+        Ok(self.offsets.get(&key).copied())
+    }
+
+    /// Whether the index was built from the file at `path` in its current
+    /// state, judging by its size and modification time.
+    pub fn is_current<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let stamp = file_stamp(&fs::metadata(path)?)?;
+        Ok(stamp == (self.data_len, self.data_modified))
+    }
+
+    /// Write the index in a compact binary format.
+    pub fn write_to<W: io::Write>(&self, mut wtr: W) -> Result<()> {
+        wtr.write_all(KEY_INDEX_MAGIC)?;
+        wtr.write_all(&[KEY_INDEX_VERSION])?;
+        wtr.write_all(&self.data_len.to_le_bytes())?;
+        wtr.write_all(&self.data_modified.to_le_bytes())?;
+        write_bytes(&mut wtr, self.pointer.as_bytes())?;
+        wtr.write_all(&(self.offsets.len() as u64).to_le_bytes())?;
+        for (key, offset) in &self.offsets {
+            write_bytes(&mut wtr, key.as_bytes())?;
+            wtr.write_all(&offset.to_le_bytes())?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Read an index written by [`write_to`](KeyIndex::write_to).
+    pub fn read_from<R: io::Read>(mut rdr: R) -> Result<KeyIndex> {
+        let mut header = [0; 21];
+        rdr.read_exact(&mut header)?;
+        if &header[..4] != KEY_INDEX_MAGIC || header[4] != KEY_INDEX_VERSION {
+            return Err(invalid_data("not a key index"));
+        }
+        let data_len = u64::from_le_bytes(header[5..13].try_into().unwrap());
+        let data_modified = u64::from_le_bytes(header[13..].try_into().unwrap());
+        let pointer = read_string(&mut rdr)?;
+        let len = read_u64(&mut rdr)?;
+        let mut offsets = HashMap::new();
+        for _ in 0..len {
+            let key = read_string(&mut rdr)?;
+            offsets.insert(key, read_u64(&mut rdr)?);
+        }
+        Ok(KeyIndex {
+            pointer,
+            data_len,
+            data_modified,
+            offsets,
+        })
+    }
+}
+
+/// A reader that looks up JSON Lines records by key.
+///
+/// The key index is kept in a sidecar file (see [`key_sidecar_path`]) and
+/// is rebuilt whenever it is missing, was built for another pointer, or the
+/// size or modification time of the data file has changed.
+#[derive(Debug)]
+pub struct KeyedReader<R> {
+    rdr: BufReader<R>,
+    index: KeyIndex,
+}
+
+impl KeyedReader<File> {
+    /// Open the JSON Lines file at the given path for lookups by the value
+    /// at `pointer`, such as `/id`.
+    ///
+    /// If the sidecar key index is not current, it is rebuilt and written
+    /// back, which fails with [`Error::DuplicateKey`] if two records share a
+    /// key.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::index::KeyedReader;
+    /// use serde_json::Value;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut rdr = KeyedReader::open("users.jsonl", "/id")?;
+    ///     if let Some(user) = rdr.lookup::<_, Value>(42)? {
+    ///         println!("{}", user["name"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P, pointer: &str) -> Result<KeyedReader<File>> {
+        let path = path.as_ref();
+        let sidecar = key_sidecar_path(path);
+        let stored = File::open(&sidecar)
+            .ok()
+            .and_then(|file| KeyIndex::read_from(BufReader::new(file)).ok());
+        let index = match stored {
+            Some(index) if index.pointer == pointer && index.is_current(path)? => index,
+            _ => {
+                let index = KeyIndex::build_from_path(path, pointer)?;
+                index.write_to(BufWriter::new(File::create(&sidecar)?))?;
+                index
+            }
+        };
+        Ok(KeyedReader::new(File::open(path)?, index))
+    }
+}
+
+impl<R: io::Read + Seek> KeyedReader<R> {
+    /// Create a reader for `rdr` using a key index of its records.
+    pub fn new(rdr: R, index: KeyIndex) -> KeyedReader<R> {
+        KeyedReader {
+            rdr: BufReader::new(rdr),
+            index,
+        }
+    }
+
+    /// The key index.
+    pub fn index(&self) -> &KeyIndex {
+        &self.index
+    }
+
+    /// Deserialize the record with the given key, or return `None` if there
+    /// is none.
+    pub fn lookup<K: Serialize, D: DeserializeOwned>(&mut self, key: K) -> Result<Option<D>> {
+        let offset = match self.index.get(key)? {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        self.rdr.seek(SeekFrom::Start(offset))?;
+        let mut iter = serde_json::Deserializer::from_reader(&mut self.rdr).into_iter();
+        match iter.next() {
+            Some(result) => Ok(Some(result?)),
+            None => Err(Error::SyntaxError {
+                position: offset,
+                msg: "expected a value",
+            }),
+        }
+    }
+}
+
+/// The size and modification time, in nanoseconds since the Unix epoch, of
+/// a data file.
+fn file_stamp(metadata: &fs::Metadata) -> Result<(u64, u64)> {
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    Ok((metadata.len(), modified))
+}
+
+fn write_bytes<W: io::Write>(wtr: &mut W, bytes: &[u8]) -> io::Result<()> {
+    wtr.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    wtr.write_all(bytes)
+}
+
+fn read_u64<R: io::Read>(rdr: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    rdr.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string<R: io::Read>(rdr: &mut R) -> Result<String> {
+    let len = read_u64(rdr)?;
+    let mut buf = Vec::new();
+    rdr.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    String::from_utf8(buf).map_err(|_| invalid_data("key index holds invalid UTF-8"))
+}
+
+fn invalid_data(msg: &'static str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::error::Error as JsonError;
+    use crate::index::{
+        key_sidecar_path, sidecar_path, IndexedReader, KeyIndex, KeyedReader, RecordIndex,
+    };
+    use crate::test_util::TempPath;
+    use crate::writer::WriterBuilder;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Row {
+        id: u32,
+        tags: Vec<String>,
+    }
+
+    fn row(id: u32) -> Row {
+        Row {
+            id,
+            tags: vec![format!("t{}", id); (id % 3) as usize],
+        }
+    }
+
+    #[test]
+    fn writer_index_matches_scan() -> Result<(), Box<dyn Error>> {
+        for json_lines in [false, true] {
+            let mut wtr = WriterBuilder::new()
+                .json_lines(json_lines)
+                .from_indexed_writer(vec![]);
+            for id in 0..500 {
+                wtr.serialize(row(id))?;
+            }
+            let (data, index) = wtr.finish()?;
+            assert_eq!(index.len(), 500);
+            assert_eq!(index, RecordIndex::build(&data[..], json_lines)?);
+
+            let mut rdr = IndexedReader::new(Cursor::new(data), index);
+            assert_eq!(rdr.get::<Row>(0)?, Some(row(0)));
+            assert_eq!(rdr.get::<Row>(499)?, Some(row(499)));
+            assert_eq!(rdr.get::<Row>(500)?, None);
+            let page = rdr.range::<Row>(250..260)?.collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(page, (250..260).map(row).collect::<Vec<_>>());
+            assert_eq!(rdr.range::<Row>(495..1000)?.count(), 5);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn indexes_records_that_are_arrays() -> Result<(), Box<dyn Error>> {
+        let data = b" [ [1], \"a,b\" ,{\"c\":[2]}]";
+        let index = RecordIndex::build(&data[..], false)?;
+        assert_eq!(
+            (0..index.len()).map(|n| index.get(n)).collect::<Vec<_>>(),
+            vec![Some(3), Some(8), Some(15)]
+        );
+        let mut rdr = IndexedReader::new(Cursor::new(&data[..]), index);
+        assert_eq!(rdr.get::<Vec<u32>>(0)?, Some(vec![1]));
+        assert_eq!(rdr.get::<String>(1)?, Some("a,b".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_index() -> Result<(), Box<dyn Error>> {
+        let index = RecordIndex::build(&b"1\n22\n333\n"[..], true)?;
+        let mut buf = Vec::new();
+        index.write_to(&mut buf)?;
+        assert_eq!(RecordIndex::read_from(&buf[..])?, index);
+        assert!(RecordIndex::read_from(&b"nonsense, not an index"[..]).is_err());
+        assert_eq!(
+            sidecar_path("data/rows.json").to_str(),
+            Some("data/rows.json.idx")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn looks_up_records_by_key() -> Result<(), Box<dyn Error>> {
+        let data = "{\"id\":1,\"name\":\"a\"}\n{\"id\":\"1\",\"name\":\"b\"}\n{\"name\":\"c\"}\n";
+        let index = KeyIndex::build(data.as_bytes(), "/id")?;
+        assert_eq!(index.len(), 2);
+        let mut buf = Vec::new();
+        index.write_to(&mut buf)?;
+        assert_eq!(KeyIndex::read_from(&buf[..])?, index);
+
+        let mut rdr = KeyedReader::new(Cursor::new(data), index);
+        let record = rdr.lookup::<_, serde_json::Value>(1)?.unwrap();
+        assert_eq!(record["name"], "a");
+        let record = rdr.lookup::<_, serde_json::Value>("1")?.unwrap();
+        assert_eq!(record["name"], "b");
+        assert!(rdr.lookup::<_, serde_json::Value>(2)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_duplicate_keys() {
+        let data = "{\"id\":1}\n{\"id\":2}\n{\"id\":1}\n";
+        match KeyIndex::build(data.as_bytes(), "/id") {
+            Err(JsonError::DuplicateKey { key, position }) => {
+                assert_eq!((key.as_str(), position), ("1", 18));
+            }
+            other => panic!("expected a duplicate key error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rebuilds_stale_sidecar() -> Result<(), Box<dyn Error>> {
+        let path = TempPath::with_contents("keyed.jsonl", "{\"id\":1,\"v\":\"old\"}\n");
+        let _sidecar = TempPath::from(key_sidecar_path(&path));
+        let mut rdr = KeyedReader::open(&path, "/id")?;
+        assert_eq!(rdr.lookup::<_, serde_json::Value>(1)?.unwrap()["v"], "old");
+        assert!(
+            KeyIndex::read_from(std::fs::File::open(key_sidecar_path(&path))?)?
+                .is_current(&path)?
+        );
+
+        std::fs::write(&path, "{\"id\":2,\"v\":\"x\"}\n{\"id\":1,\"v\":\"new\"}\n")?;
+        let mut rdr = KeyedReader::open(&path, "/id")?;
+        assert_eq!(rdr.lookup::<_, serde_json::Value>(1)?.unwrap()["v"], "new");
+        Ok(())
+    }
+}
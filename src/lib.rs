@@ -1,6 +1,30 @@
+#[cfg(feature = "async")]
+pub mod async_reader;
+#[cfg(feature = "async")]
+pub mod async_writer;
+#[cfg(feature = "bgzf")]
+pub mod bgzf;
 pub mod bytes_object;
+pub mod canonical;
+#[cfg(feature = "codec")]
+pub mod codec;
+pub mod decoder;
 pub mod error;
+mod follow;
+pub mod index;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod reader;
+pub mod recover;
+pub mod reverse;
+pub mod sorted;
+#[cfg(test)]
+mod test_util;
 pub mod writer;
+#[cfg(feature = "zstd")]
+pub mod zstd;
 
+pub use crate::reader::{Reader, ReaderBuilder};
 pub use crate::writer::{Writer, WriterBuilder};
@@ -0,0 +1,451 @@
+//! Zero-copy reading of JSON arrays and JSON Lines from memory-mapped files.
+//!
+//! An [`MmapReader`], built with
+//! [`ReaderBuilder::from_path_mmap`](crate::ReaderBuilder::from_path_mmap),
+//! maps the whole file. It splits records with the same [`RecordDecoder`]
+//! as a [`Reader`](crate::Reader) and honours the same builder options, but the
+//! records it yields borrow straight from the mapping, and any byte offset
+//! can be read without seeking. Files that cannot be mapped, such as pipes,
+//! are read into memory instead.
+//!
+//! Mapping a file is `unsafe`, since the mapped data changes if the file is
+//! modified while it is mapped, so the constructors are `unsafe fn`s.
+use crate::decoder::RecordDecoder;
+use crate::error::{Error, Limit, RecordError, Result};
+use crate::reader::{ErrorPolicy, ReaderBuilder};
+use memmap2::Mmap;
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{self, Read},
+    marker::PhantomData,
+    path::Path,
+};
+
+#[derive(Debug)]
+enum Data {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl Data {
+    /// Map `file`, or read it into memory if it cannot be mapped.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified while the mapping is alive.
+    unsafe fn new(mut file: File) -> io::Result<Data> {
+        let metadata = file.metadata()?;
+        // Mapping an empty file fails on some platforms.
+        if metadata.is_file() && metadata.len() > 0 {
+            Ok(Data::Mapped(Mmap::map(&file)?))
+        } else {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            Ok(Data::Buffered(buf))
+        }
+    }
+}
+
+/// A reader of JSON records from a memory-mapped file.
+#[derive(Debug)]
+pub struct MmapReader {
+    data: Data,
+    builder: ReaderBuilder,
+}
+
+impl MmapReader {
+    /// Map `file` and read it with the configuration of `builder`.
+    ///
+    /// # Safety
+    ///
+    /// See [`ReaderBuilder::from_path_mmap`].
+    pub(crate) unsafe fn new(builder: &ReaderBuilder, file: File) -> Result<MmapReader> {
+        Ok(MmapReader {
+            data: Data::new(file)?,
+            builder: builder.clone(),
+        })
+    }
+
+    /// Map the JSON array file at the given path with a default
+    /// configuration.
+    ///
+    /// To read JSON Lines or customize reading, use
+    /// [`ReaderBuilder::from_path_mmap`].
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified while the reader is
+    /// alive; see [`ReaderBuilder::from_path_mmap`].
+    pub unsafe fn from_path<P: AsRef<Path>>(path: P) -> Result<MmapReader> {
+        ReaderBuilder::new().from_path_mmap(path)
+    }
+
+    /// Whether the file is memory-mapped, rather than read into memory.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.data, Data::Mapped(_))
+    }
+
+    /// The raw contents of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.data {
+            Data::Mapped(mmap) => mmap,
+            Data::Buffered(buf) => buf,
+        }
+    }
+
+    /// Returns an iterator over records deserialized in place.
+    ///
+    /// Each item yielded by this iterator is a `Result<D, Error>`. The
+    /// records may borrow from the reader, so `&str` fields without escape
+    /// sequences are read without copying. Malformed records are handled
+    /// according to the reader's [`ErrorPolicy`], as by
+    /// [`Reader::deserialize`](crate::Reader::deserialize).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::mmap::MmapReader;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Row<'a> {
+    ///     city: &'a str,
+    /// }
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     // SAFETY: nothing modifies the file while it is read.
+    ///     let rdr = unsafe { MmapReader::from_path("cities.json")? };
+    ///     for record in rdr.deserialize::<Row>() {
+    ///         println!("{}", record?.city);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn deserialize<'a, D: Deserialize<'a>>(&'a self) -> DeserializeMmapIter<'a, D> {
+        DeserializeMmapIter {
+            data: self.as_bytes(),
+            decoder: self.builder.decoder(),
+            error_policy: self.builder.error_policy,
+            max_records: self.builder.max_records,
+            max_input_bytes: self.builder.max_input_bytes,
+            done: false,
+            index: 0,
+            skipping: None,
+            skipped: 0,
+            errors: Vec::new(),
+            _priv: PhantomData,
+        }
+    }
+
+    /// Deserialize the record that starts at byte `offset`.
+    ///
+    /// Leading whitespace is skipped. This is useful together with offsets
+    /// from [`BytesObject::position`](crate::bytes_object::BytesObject::position)
+    /// or an index built when the file was written.
+    pub fn deserialize_at<'a, D: Deserialize<'a>>(&'a self, offset: u64) -> Result<D> {
+        let data = self.as_bytes();
+        let start = usize::try_from(offset)
+            .ok()
+            .filter(|&start| start <= data.len())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        // Whatever follows the record, such as `,` or `]` in an array, ends
+        // it like the end of a line.
+        let mut decoder = RecordDecoder::json_lines();
+        decoder.set_offset(offset);
+        match decoder.next_record_in(&data[start..])? {
+            Some((_, bytes)) => Ok(serde_json::from_slice(bytes)?),
+            None => Err(Error::SyntaxError {
+                position: data.len() as u64,
+                msg: "expected a value",
+            }),
+        }
+    }
+}
+
+/// A borrowed iterator over the records of an [`MmapReader`].
+///
+/// The lifetime parameter `'a` refers to the lifetime of the reader, which
+/// records may borrow from.
+#[derive(Debug)]
+pub struct DeserializeMmapIter<'a, D> {
+    data: &'a [u8],
+    decoder: RecordDecoder,
+    error_policy: ErrorPolicy,
+    max_records: Option<u64>,
+    max_input_bytes: Option<u64>,
+    done: bool,
+    /// The number of records read so far, including malformed ones.
+    index: u64,
+    /// The error being skipped past, while looking for the next record.
+    skipping: Option<Error>,
+    skipped: u64,
+    errors: Vec<RecordError>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<'a, D: Deserialize<'a>> DeserializeMmapIter<'a, D> {
+    /// Read the raw bytes of the next record, with its position.
+    ///
+    /// Records that cannot be delimited are handled according to the
+    /// reader's [`ErrorPolicy`].
+    pub fn read_slice(&mut self) -> Result<Option<(u64, &'a [u8])>> {
+        if self.done {
+            return Ok(None);
+        }
+        if let Some(max) = self.max_input_bytes {
+            if self.data.len() as u64 > max {
+                self.done = true;
+                return Err(Error::LimitExceeded {
+                    limit: Limit::TotalBytes(max),
+                    position: max,
+                });
+            }
+        }
+        loop {
+            if let Some(err) = self.skipping.take() {
+                if let Some((position, bytes)) = self.decoder.skip_invalid_in(self.data) {
+                    self.check_record_count(position)?;
+                    self.index += 1;
+                    self.reject(position, bytes, err);
+                }
+                continue;
+            }
+            match self.decoder.next_record_in(self.data) {
+                Ok(Some((position, bytes))) => {
+                    self.check_record_count(position)?;
+                    self.index += 1;
+                    return Ok(Some((position, bytes)));
+                }
+                Ok(None) => {
+                    self.done = true;
+                    return Ok(None);
+                }
+                Err(err)
+                    if self.error_policy != ErrorPolicy::Fail
+                        && self.decoder.can_recover()
+                        && !matches!(err, Error::LimitExceeded { .. }) =>
+                {
+                    self.skipping = Some(err);
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// The number of malformed records skipped so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// The malformed records skipped so far with [`ErrorPolicy::Collect`].
+    pub fn errors(&self) -> &[RecordError] {
+        &self.errors
+    }
+
+    fn reject(&mut self, position: u64, bytes: &[u8], error: Error) {
+        self.skipped += 1;
+        if self.error_policy == ErrorPolicy::Collect {
+            self.errors.push(RecordError {
+                index: self.index - 1,
+                position,
+                bytes: bytes.to_vec(),
+                error,
+            });
+        }
+    }
+
+    fn check_record_count(&mut self, position: u64) -> Result<()> {
+        match self.max_records {
+            Some(max) if self.index >= max => {
+                self.done = true;
+                Err(Error::LimitExceeded {
+                    limit: Limit::Records(max),
+                    position,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a, D: Deserialize<'a>> Iterator for DeserializeMmapIter<'a, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        loop {
+            let (position, bytes) = match self.read_slice() {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            match serde_json::from_slice(bytes) {
+                Ok(record) => return Some(Ok(record)),
+                Err(err) if self.error_policy != ErrorPolicy::Fail => {
+                    self.reject(position, bytes, err.into())
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+
+    use crate::error::{Error as JsonError, Limit};
+    use crate::mmap::MmapReader;
+    use crate::reader::{ErrorPolicy, TrailingData};
+    use crate::test_util::TempPath;
+    use crate::ReaderBuilder;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row<'a> {
+        city: &'a str,
+        #[serde(rename = "popcount")]
+        population: u64,
+    }
+
+    fn map(builder: &ReaderBuilder, file: &TempPath) -> MmapReader {
+        // SAFETY: the test files are not modified while they are mapped.
+        unsafe { builder.from_path_mmap(file).unwrap() }
+    }
+
+    #[test]
+    fn borrows_from_mapped_array() -> Result<(), Box<dyn Error>> {
+        let file = TempPath::with_contents(
+            "array.json",
+            r#" [{"city":"Boston","popcount":4628910} , {"city":"Concord","popcount":42695}]
+"#,
+        );
+        let rdr = map(&ReaderBuilder::new(), &file);
+        assert!(rdr.is_mapped());
+        let rows = rdr.deserialize::<Row>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    city: "Boston",
+                    population: 4628910
+                },
+                Row {
+                    city: "Concord",
+                    population: 42695
+                },
+            ]
+        );
+        let data = rdr.as_bytes().as_ptr_range();
+        assert!(data.contains(&rows[1].city.as_ptr()));
+        let row = rdr.deserialize_at::<Row>(41)?;
+        assert_eq!(row.city, "Concord");
+        assert_eq!(rdr.deserialize_at::<u32>(0).ok(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_json_lines() -> Result<(), Box<dyn Error>> {
+        let file = TempPath::with_contents("lines.json", "1\n2\n\n3\n");
+        let rdr = map(ReaderBuilder::new().json_lines(true), &file);
+        let values = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn frames_records_like_the_reader() -> Result<(), Box<dyn Error>> {
+        let inputs = [
+            "[1,2 3]",
+            "[1] 2",
+            "1",
+            "[1,",
+            "[1,2",
+            "[1,\"}\"]",
+            "\u{feff}[1]",
+        ];
+        for input in inputs {
+            let file = TempPath::with_contents("invalid.json", input);
+            let rdr = map(&ReaderBuilder::new(), &file);
+            let mapped = rdr
+                .deserialize::<serde_json::Value>()
+                .map(|result| result.map_err(|err| err.to_string()))
+                .collect::<Vec<_>>();
+            let streamed = ReaderBuilder::new()
+                .from_reader(input.as_bytes())
+                .deserialize::<serde_json::Value>()
+                .map(|result| result.map_err(|err| err.to_string()))
+                .collect::<Vec<_>>();
+            assert_eq!(mapped, streamed, "{}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn honours_the_reader_builder() -> Result<(), Box<dyn Error>> {
+        let file = TempPath::with_contents("builder.json", "\u{feff}[1, x, 3, \"4\"]\n[5]");
+        let rdr = map(
+            ReaderBuilder::new()
+                .error_policy(ErrorPolicy::Collect)
+                .skip_bom(true)
+                .trailing_data(TrailingData::Concatenated),
+            &file,
+        );
+        let mut iter = rdr.deserialize::<u32>();
+        let values = iter.by_ref().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 3, 5]);
+        assert_eq!(iter.skipped(), 2);
+        let errors = iter.errors();
+        assert_eq!((errors[0].index(), errors[0].position()), (1, 7));
+        assert_eq!((errors[1].index(), errors[1].bytes()), (3, &b"\"4\""[..]));
+
+        let file = TempPath::with_contents("limits.json", "{\"a\":[1]}\n{\"a\":[[2]]}\n");
+        let rdr = map(ReaderBuilder::new().json_lines(true).max_depth(2), &file);
+        let results = rdr.deserialize::<serde_json::Value>().collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(JsonError::LimitExceeded {
+                limit: Limit::Depth(2),
+                ..
+            })
+        ));
+
+        let rdr = map(ReaderBuilder::new().json_lines(true).max_records(1), &file);
+        assert_eq!(rdr.deserialize::<serde_json::Value>().count(), 2);
+        let rdr = map(
+            ReaderBuilder::new().json_lines(true).max_input_bytes(4),
+            &file,
+        );
+        assert!(rdr
+            .deserialize::<serde_json::Value>()
+            .next()
+            .unwrap()
+            .is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn falls_back_for_non_regular_files() -> Result<(), Box<dyn Error>> {
+        // SAFETY: `/dev/null` is read into memory rather than mapped.
+        let rdr = unsafe {
+            ReaderBuilder::new()
+                .json_lines(true)
+                .from_path_mmap("/dev/null")?
+        };
+        assert!(!rdr.is_mapped());
+        assert_eq!(rdr.deserialize::<u32>().count(), 0);
+        Ok(())
+    }
+}
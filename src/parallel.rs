@@ -0,0 +1,694 @@
+//! Parallel deserialization and serialization of JSON arrays and JSON Lines.
+//!
+//! Finding record boundaries is cheap compared to deserializing records, so
+//! a [`ParallelReader`] splits its input into [`BytesObject`]s on the calling
+//! thread and deserializes batches of them on the rayon thread pool. Records
+//! are still yielded in input order, and the number of batches in flight is
+//! bounded, which bounds memory use.
+//!
+//! On the write side, a [`ParallelWriter`] serializes batches of records on
+//! the rayon thread pool and writes them in submission order.
+use crate::bytes_object::BytesObject;
+use crate::error::{Error, Result};
+use crate::reader::{Reader, ReaderBuilder};
+use crate::writer::{Writer, WriterBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::VecDeque,
+    io,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{self, Receiver},
+    thread, vec,
+};
+
+/// A reader that deserializes records on the rayon thread pool.
+///
+/// A `ParallelReader` is built with
+/// [`ReaderBuilder::from_parallel_reader`](crate::ReaderBuilder::from_parallel_reader).
+/// It splits its input into records on the calling thread with a [`Reader`]
+/// built from the same builder, so the error policy, limits and document
+/// checks all apply, and deserializes batches of records on the pool. The
+/// splitting runs ahead of the records yielded until enough batches are in
+/// flight, which bounds memory use.
+///
+/// Records that fail to deserialize are handed to the error policy when
+/// they are yielded, so with [`ErrorPolicy::Collect`](crate::reader::ErrorPolicy::Collect)
+/// or a dead-letter writer they may be recorded after malformed records
+/// that come later in the input.
+#[derive(Debug)]
+pub struct ParallelReader<R> {
+    rdr: Reader<R>,
+    batch_records: usize,
+    batch_bytes: usize,
+    max_batches_in_flight: usize,
+}
+
+impl<R: io::Read> ParallelReader<R> {
+    pub(crate) fn new(builder: &ReaderBuilder, rdr: R) -> ParallelReader<R> {
+        ParallelReader {
+            rdr: builder.from_reader(rdr),
+            batch_records: 1 << 10,
+            batch_bytes: 1 << 20,
+            max_batches_in_flight: 2 * rayon::current_num_threads(),
+        }
+    }
+
+    /// Create a new parallel JSON array reader with a default configuration.
+    ///
+    /// To read JSON Lines or customize reading, use
+    /// [`ReaderBuilder::from_parallel_reader`](crate::ReaderBuilder::from_parallel_reader).
+    pub fn from_reader(rdr: R) -> ParallelReader<R> {
+        ReaderBuilder::new().from_parallel_reader(rdr)
+    }
+
+    /// The maximum number of records deserialized together as one batch.
+    ///
+    /// This defaults to `1024`.
+    pub fn batch_records(&mut self, records: usize) -> &mut ParallelReader<R> {
+        self.batch_records = records.max(1);
+        self
+    }
+
+    /// The number of record bytes after which a batch is cut, even if it
+    /// holds fewer than [`batch_records`](ParallelReader::batch_records)
+    /// records.
+    ///
+    /// This defaults to 1 MiB.
+    pub fn batch_bytes(&mut self, bytes: usize) -> &mut ParallelReader<R> {
+        self.batch_bytes = bytes.max(1);
+        self
+    }
+
+    /// The maximum number of batches that have been split off but not yet
+    /// yielded.
+    ///
+    /// Together with the batch size this bounds the memory in use. This
+    /// defaults to twice the size of the rayon thread pool.
+    pub fn max_batches_in_flight(&mut self, batches: usize) -> &mut ParallelReader<R> {
+        self.max_batches_in_flight = batches.max(1);
+        self
+    }
+
+    /// Return a mutable reference to the reader that splits the input, for
+    /// instance to set a dead-letter writer with
+    /// [`Reader::set_dead_letter`].
+    pub fn reader_mut(&mut self) -> &mut Reader<R> {
+        &mut self.rdr
+    }
+
+    /// Return an iterator over deserialized records in input order.
+    ///
+    /// Each item yielded by this iterator is a `Result<D, Error>`. With the
+    /// default error policy, the iterator ends after the first error;
+    /// otherwise malformed records are skipped and only errors that end
+    /// reading are yielded. Dropping the iterator stops reading; batches
+    /// still on the pool are discarded once they are done.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = (0..10_000).map(|n| format!("{}\n", n)).collect::<String>();
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .from_parallel_reader(std::io::Cursor::new(data));
+    ///     rdr.batch_records(100);
+    ///     let records = rdr.into_deserialize::<u32>();
+    ///     assert_eq!(records.count(), 10_000);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_deserialize<D>(self) -> DeserializeRecordsParallelIter<R, D>
+    where
+        D: DeserializeOwned + Send + 'static,
+    {
+        DeserializeRecordsParallelIter {
+            rdr: self.rdr,
+            batch_records: self.batch_records,
+            batch_bytes: self.batch_bytes,
+            max_batches_in_flight: self.max_batches_in_flight,
+            pending: VecDeque::new(),
+            current: Vec::new().into_iter(),
+            split_done: false,
+            error: None,
+            done: false,
+            _priv: PhantomData,
+        }
+    }
+}
+
+/// A record that failed to deserialize on the thread pool.
+#[derive(Debug)]
+struct Failed {
+    index: u64,
+    obj: BytesObject,
+    error: Error,
+}
+
+/// The outcome of deserializing one batch, record by record.
+type DeserializedBatch<D> = Vec<std::result::Result<D, Failed>>;
+
+/// An owned iterator over records deserialized by a [`ParallelReader`].
+#[derive(Debug)]
+pub struct DeserializeRecordsParallelIter<R, D> {
+    rdr: Reader<R>,
+    batch_records: usize,
+    batch_bytes: usize,
+    max_batches_in_flight: usize,
+    pending: VecDeque<Receiver<DeserializedBatch<D>>>,
+    current: vec::IntoIter<std::result::Result<D, Failed>>,
+    /// Set once the reader has no more records.
+    split_done: bool,
+    /// The error that ended splitting, yielded after the batches before it.
+    error: Option<Error>,
+    done: bool,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<R: io::Read, D: DeserializeOwned + Send + 'static> DeserializeRecordsParallelIter<R, D> {
+    /// Return a reference to the reader that splits the input.
+    ///
+    /// It reads ahead of the records yielded so far.
+    pub fn reader(&self) -> &Reader<R> {
+        &self.rdr
+    }
+
+    /// Split records off the input and queue them in batches until enough
+    /// batches are in flight or the input ends.
+    fn split(&mut self) {
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        while !self.split_done && self.pending.len() < self.max_batches_in_flight {
+            match self.rdr.read_object() {
+                Ok(Some(obj)) => {
+                    batch_bytes += obj.as_bytes().len();
+                    batch.push((self.rdr.index() - 1, obj));
+                    if batch.len() >= self.batch_records || batch_bytes >= self.batch_bytes {
+                        self.send_batch(std::mem::take(&mut batch));
+                        batch_bytes = 0;
+                    }
+                }
+                Ok(None) => self.split_done = true,
+                Err(err) => {
+                    self.split_done = true;
+                    self.error = Some(err);
+                }
+            }
+        }
+        if !batch.is_empty() {
+            self.send_batch(batch);
+        }
+    }
+
+    /// Queue `batch` for deserialization on the thread pool.
+    fn send_batch(&mut self, batch: Vec<(u64, BytesObject)>) {
+        let (tx, rx) = mpsc::sync_channel(1);
+        rayon::spawn(move || {
+            let records = panic::catch_unwind(AssertUnwindSafe(|| {
+                batch.into_iter().map(deserialize).collect::<Vec<_>>()
+            }));
+            // On a panic the sender is dropped without sending.
+            if let Ok(records) = records {
+                let _ = tx.send(records);
+            }
+        });
+        self.pending.push_back(rx);
+    }
+}
+
+fn deserialize<D: DeserializeOwned>(
+    (index, obj): (u64, BytesObject),
+) -> std::result::Result<D, Failed> {
+    let result = obj.deserialize();
+    result.map_err(|error| Failed { index, obj, error })
+}
+
+impl<R: io::Read, D: DeserializeOwned + Send + 'static> Iterator
+    for DeserializeRecordsParallelIter<R, D>
+{
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        while !self.done {
+            match self.current.next() {
+                Some(Ok(record)) => return Some(Ok(record)),
+                Some(Err(failed)) => {
+                    let rejected =
+                        self.rdr
+                            .reject_deserialized(failed.index, failed.obj, failed.error);
+                    if let Err(err) = rejected {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+                None => {}
+            }
+            self.split();
+            let batch = match self.pending.pop_front() {
+                Some(batch) => batch,
+                None => {
+                    self.done = true;
+                    return self.error.take().map(Err);
+                }
+            };
+            match batch.recv() {
+                Ok(records) => self.current = records.into_iter(),
+                Err(_) => {
+                    self.done = true;
+                    return Some(Err(worker_panicked("deserializing")));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The number of records serialized together as one batch by a
+/// [`ParallelWriter`].
+const WRITE_BATCH_RECORDS: usize = 1 << 10;
+
+/// A record waiting to be serialized on the thread pool.
+type SerializeRecord = Box<dyn FnOnce(&mut Vec<u8>) -> serde_json::Result<()> + Send>;
+
+/// The serialized records of one batch, back to back.
+#[derive(Debug)]
+struct SerializedBatch {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+    error: Option<Error>,
+}
+
+/// A writer that serializes records on the rayon thread pool.
+///
+/// Records are collected into batches, each batch is serialized on the
+/// pool, and the results are written to the underlying writer in submission
+/// order with the same framing as a [`Writer`] built from the same
+/// [`WriterBuilder`]. The number of batches in flight is bounded by twice the
+/// size of the rayon thread pool; once that many are outstanding,
+/// [`serialize`](ParallelWriter::serialize) waits for the oldest one.
+///
+/// Because records are serialized later, a serialization error is returned
+/// by a subsequent call to `serialize`, [`flush`](ParallelWriter::flush) or
+/// [`close`](ParallelWriter::close). Neither the failed record nor any
+/// record queued after it is written, so the output never has gaps: after
+/// an error, `serialize` and `flush` keep failing, and `close` finishes the
+/// output with the records before the failed one.
+///
+/// Dropping a `ParallelWriter` waits for all outstanding batches and closes
+/// the output, ignoring errors. Call `close` or `into_inner` to observe
+/// them.
+pub struct ParallelWriter<W: io::Write> {
+    wtr: Option<Writer<W>>,
+    batch: Vec<SerializeRecord>,
+    pending: VecDeque<Receiver<SerializedBatch>>,
+    max_batches_in_flight: usize,
+    /// Set once a record failed, after which nothing more is written.
+    failed: bool,
+}
+
+impl<W: io::Write> std::fmt::Debug for ParallelWriter<W>
+where
+    W: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParallelWriter")
+            .field("wtr", &self.wtr)
+            .field("batch", &self.batch.len())
+            .field("pending", &self.pending.len())
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+impl<W: io::Write> Drop for ParallelWriter<W> {
+    fn drop(&mut self) {
+        if self.wtr.is_some() && !thread::panicking() {
+            let _ = self.write_all_batches();
+        }
+    }
+}
+
+impl<W: io::Write> ParallelWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> ParallelWriter<W> {
+        ParallelWriter {
+            wtr: Some(builder.from_writer(wtr)),
+            batch: Vec::with_capacity(WRITE_BATCH_RECORDS),
+            pending: VecDeque::new(),
+            max_batches_in_flight: 2 * rayon::current_num_threads(),
+            failed: false,
+        }
+    }
+
+    /// Create a new parallel JSON array writer with a default configuration.
+    ///
+    /// To write JSON Lines, use
+    /// [`WriterBuilder::from_parallel_writer`](crate::WriterBuilder::from_parallel_writer).
+    pub fn from_writer(wtr: W) -> ParallelWriter<W> {
+        WriterBuilder::new().from_parallel_writer(wtr)
+    }
+
+    /// Queue a single record for serialization using Serde.
+    ///
+    /// Records are written in the order they are queued. Since serialization
+    /// happens on another thread, the record must be owned and `Send`.
+    pub fn serialize<S: Serialize + Send + 'static>(&mut self, record: S) -> Result<()> {
+        self.check_failed()?;
+        let format = self.wtr.as_ref().unwrap().format();
+        self.batch
+            .push(Box::new(move |buf| format.to_writer(buf, &record)));
+        if self.batch.len() >= WRITE_BATCH_RECORDS {
+            self.send_batch();
+        }
+        self.write_finished_batches()
+    }
+
+    /// Serialize and write all queued records and flush the underlying
+    /// writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.check_failed()?;
+        self.write_all_batches()?;
+        Ok(self.wtr.as_mut().unwrap().flush()?)
+    }
+
+    /// Serialize and write all queued records and finish the output.
+    ///
+    /// For arrays this writes the closing `]`. If a record failed, the
+    /// output ends with the records before it, and this returns the error
+    /// unless an earlier call already did.
+    pub fn close(&mut self) -> Result<()> {
+        let written = self.write_all_batches();
+        self.wtr.as_mut().unwrap().close()?;
+        written
+    }
+
+    /// Close the writer and return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.close()?;
+        self.wtr.take().unwrap().into_inner()
+    }
+
+    fn send_batch(&mut self) {
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(WRITE_BATCH_RECORDS));
+        let (tx, rx) = mpsc::sync_channel(1);
+        rayon::spawn(move || {
+            let mut serialized = SerializedBatch {
+                bytes: Vec::new(),
+                ends: Vec::with_capacity(batch.len()),
+                error: None,
+            };
+            for record in batch {
+                let bytes = &mut serialized.bytes;
+                let error = match panic::catch_unwind(AssertUnwindSafe(|| record(bytes))) {
+                    Ok(Ok(())) => None,
+                    Ok(Err(err)) => Some(err.into()),
+                    Err(_) => Some(worker_panicked("serializing")),
+                };
+                if error.is_some() {
+                    serialized.error = error;
+                    break;
+                }
+                serialized.ends.push(serialized.bytes.len());
+            }
+            let _ = tx.send(serialized);
+        });
+        self.pending.push_back(rx);
+    }
+
+    /// Write the batches at the front of the queue that are already
+    /// serialized, waiting only if too many batches are in flight.
+    fn write_finished_batches(&mut self) -> Result<()> {
+        while let Some(rx) = self.pending.front() {
+            let serialized = if self.pending.len() > self.max_batches_in_flight {
+                rx.recv().ok()
+            } else {
+                match rx.try_recv() {
+                    Ok(serialized) => Some(serialized),
+                    Err(mpsc::TryRecvError::Empty) => return Ok(()),
+                    Err(mpsc::TryRecvError::Disconnected) => None,
+                }
+            };
+            self.pending.pop_front();
+            self.write_batch(serialized)?;
+        }
+        Ok(())
+    }
+
+    fn write_all_batches(&mut self) -> Result<()> {
+        if !self.batch.is_empty() {
+            self.send_batch();
+        }
+        while let Some(rx) = self.pending.pop_front() {
+            self.write_batch(rx.recv().ok())?;
+        }
+        Ok(())
+    }
+
+    /// Write a serialized batch, or discard it if a record failed before.
+    ///
+    /// Returns the error of the first record that fails.
+    fn write_batch(&mut self, serialized: Option<SerializedBatch>) -> Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+        let result = self.write_serialized(serialized);
+        self.failed = result.is_err();
+        result
+    }
+
+    fn write_serialized(&mut self, serialized: Option<SerializedBatch>) -> Result<()> {
+        // The sender is only dropped without sending if serialization
+        // panicked on the pool.
+        let serialized = serialized.ok_or_else(|| worker_panicked("serializing"))?;
+        let wtr = self.wtr.as_mut().unwrap();
+        let mut start = 0;
+        for &end in &serialized.ends {
+            wtr.write_raw(&serialized.bytes[start..end])?;
+            start = end;
+        }
+        match serialized.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn check_failed(&self) -> Result<()> {
+        if self.failed {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::Other,
+                "an earlier record failed, so no more records are written",
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn worker_panicked(task: &str) -> Error {
+    Error::IoError(io::Error::new(
+        io::ErrorKind::Other,
+        format!("a rayon worker panicked while {} records", task),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::parallel::{ParallelReader, ParallelWriter};
+    use crate::reader::{ErrorPolicy, ReaderBuilder, TrailingData};
+    use crate::writer::WriterBuilder;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        id: u32,
+        name: String,
+    }
+
+    fn json_lines(n: u32) -> String {
+        (0..n)
+            .map(|id| format!("{{\"id\":{},\"name\":\"row {}\"}}\n", id, id))
+            .collect()
+    }
+
+    #[test]
+    fn preserves_order() -> Result<(), Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_parallel_reader(Cursor::new(json_lines(20_000)));
+        rdr.batch_records(7).max_batches_in_flight(3);
+        let ids = rdr
+            .into_deserialize::<Row>()
+            .map(|row| row.map(|row| row.id))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ids, (0..20_000).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_arrays() -> Result<(), Box<dyn Error>> {
+        let data = serde_json::to_vec(&(0..5_000).collect::<Vec<u64>>())?;
+        let mut rdr = ParallelReader::from_reader(Cursor::new(data));
+        rdr.batch_bytes(100);
+        let values = rdr
+            .into_deserialize::<u64>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, (0..5_000).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn stops_after_first_error() {
+        let data = format!("{}{{\"id\":\"x\"}}\n{}", json_lines(10), json_lines(10));
+        let results = ParallelReader::from_reader(Cursor::new(data))
+            .into_deserialize::<Row>()
+            .collect::<Vec<_>>();
+        // The data is JSON Lines but the reader expects an array.
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+
+        let data = format!("{}{{\"id\":\"x\"}}\n{}", json_lines(10), json_lines(10));
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_parallel_reader(Cursor::new(data));
+        rdr.batch_records(3);
+        let results = rdr.into_deserialize::<Row>().collect::<Vec<_>>();
+        assert_eq!(results.len(), 11);
+        assert!(results[..10].iter().all(|row| row.is_ok()));
+        assert!(results[10].is_err());
+    }
+
+    #[test]
+    fn honours_the_reader_builder() -> Result<(), Box<dyn Error>> {
+        let data = format!(
+            "{}{{\"id\":\"x\"}}\n{{\"id\":\n{}",
+            json_lines(10),
+            json_lines(10)
+        );
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .error_policy(ErrorPolicy::Collect)
+            .from_parallel_reader(Cursor::new(data));
+        rdr.batch_records(3);
+        let mut iter = rdr.into_deserialize::<Row>();
+        let ids = iter
+            .by_ref()
+            .map(|row| row.map(|row| row.id))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ids, (0..10).chain(0..10).collect::<Vec<_>>());
+        let errors = iter.reader().errors();
+        let mut indexes = errors.iter().map(|err| err.index()).collect::<Vec<_>>();
+        indexes.sort_unstable();
+        assert_eq!(indexes, [10, 11]);
+
+        let results = ReaderBuilder::new()
+            .json_lines(true)
+            .max_records(5)
+            .from_parallel_reader(Cursor::new(json_lines(10)))
+            .into_deserialize::<Row>()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 6);
+        assert!(results[5].is_err());
+
+        let results = ReaderBuilder::new()
+            .trailing_data(TrailingData::Ignore)
+            .skip_bom(true)
+            .from_parallel_reader(Cursor::new("\u{feff}[1, 2] x"))
+            .into_deserialize::<u32>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(results, [1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn can_be_dropped_early() {
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_parallel_reader(Cursor::new(json_lines(10_000)));
+        rdr.batch_records(1).max_batches_in_flight(1);
+        let mut iter = rdr.into_deserialize::<Row>();
+        assert_eq!(iter.next().unwrap().unwrap().id, 0);
+        drop(iter);
+    }
+
+    #[test]
+    fn writes_like_writer() -> Result<(), Box<dyn Error>> {
+        for json_lines in [false, true] {
+            let mut builder = WriterBuilder::new();
+            builder.json_lines(json_lines);
+            let mut expected = builder.from_writer(vec![]);
+            let mut wtr = builder.from_parallel_writer(vec![]);
+            for id in 0..5_000 {
+                expected.serialize((id, format!("row {}", id)))?;
+                wtr.serialize((id, format!("row {}", id)))?;
+            }
+            assert_eq!(wtr.into_inner()?, expected.into_inner()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reports_serialization_errors() -> Result<(), Box<dyn Error>> {
+        let mut wtr = ParallelWriter::from_writer(vec![]);
+        wtr.serialize(BTreeMap::from([("a".to_string(), 1)]))?;
+        wtr.serialize(BTreeMap::from([("b".to_string(), 2)]))?;
+        let mut invalid = BTreeMap::new();
+        invalid.insert(vec![1u8], 3);
+        // The batch is only serialized on flush, so the error shows up there.
+        wtr.serialize(BTreeMap::from([("c".to_string(), invalid)]))?;
+        assert!(wtr.flush().is_err());
+        assert_eq!(wtr.into_inner()?, br#"[{"a":1},{"b":2}]"#);
+        Ok(())
+    }
+
+    #[test]
+    fn writes_nothing_after_a_failed_record() -> Result<(), Box<dyn Error>> {
+        let mut wtr = ParallelWriter::from_writer(vec![]);
+        let mut results = Vec::new();
+        // Keys must be strings, so only the record with a key fails, while
+        // the batches after it serialize fine.
+        for n in 0..5_000 {
+            let mut record = BTreeMap::new();
+            if n == 10 {
+                record.insert(vec![1u8], n);
+            }
+            results.push(wtr.serialize(record).is_ok());
+        }
+        results.push(wtr.flush().is_ok());
+        let first_error = results.iter().position(|ok| !ok).unwrap();
+        assert!(results[first_error..].iter().all(|ok| !ok));
+        assert!(wtr.serialize(BTreeMap::<Vec<u8>, u32>::new()).is_err());
+        assert_eq!(
+            wtr.into_inner()?,
+            format!("[{}{{}}]", "{},".repeat(9)).as_bytes()
+        );
+        Ok(())
+    }
+
+    /// Panics when serialized.
+    struct Bomb;
+
+    impl serde::Serialize for Bomb {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            panic!("boom")
+        }
+    }
+
+    #[test]
+    fn reports_panics_while_serializing() -> Result<(), Box<dyn Error>> {
+        let mut wtr = ParallelWriter::from_writer(vec![]);
+        wtr.serialize(Some(Bomb))?;
+        assert!(wtr.flush().is_err());
+        assert_eq!(wtr.into_inner()?, b"[]");
+        Ok(())
+    }
+}
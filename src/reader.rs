@@ -1,421 +1,1757 @@
-// use std::{io, marker::PhantomData};
-
-// use serde::de::DeserializeOwned;
-
-// use crate::error::Result;
-
-// #[derive(Debug)]
-// pub struct ReaderBuilder {
-//     capacity: usize,
-//     is_json_lines: bool,
-// }
-
-// impl Default for ReaderBuilder {
-//     fn default() -> Self {
-//         ReaderBuilder {
-//             capacity: 8 * (1 << 10),
-//             is_json_lines: false,
-//         }
-//     }
-// }
-
-// impl ReaderBuilder {
-//     /// Create a new builder for configuring JSON reading.
-//     ///
-//     /// To convert a builder into a reader, call one of the methods starting
-//     /// with `from_`.
-//     pub fn new() -> ReaderBuilder {
-//         ReaderBuilder::default()
-//     }
-//     /// Build a CSV parser from this configuration that reads data from `rdr`.
-//     ///
-//     /// Note that the CSV reader is buffered automatically, so you should not
-//     /// wrap `rdr` in a buffered reader like `io::BufReader`.
-//     ///
-//     /// # Example
-//     ///
-//     /// ```
-//     /// use std::error::Error;
-//     /// use csv::ReaderBuilder;
-//     ///
-//     /// # fn main() { example().unwrap(); }
-//     /// fn example() -> Result<(), Box<dyn Error>> {
-//     ///     let data = "\
-//     /// city,country,pop
-//     /// Boston,United States,4628910
-//     /// Concord,United States,42695
-//     /// ";
-//     ///     let mut rdr = ReaderBuilder::new().from_reader(data.as_bytes());
-//     ///     for result in rdr.records() {
-//     ///         let record = result?;
-//     ///         println!("{:?}", record);
-//     ///     }
-//     ///     Ok(())
-//     /// }
-//     /// ```
-//     pub fn from_reader<R: io::Read>(&self, rdr: R) -> Reader<R> {
-//         Reader::new(self, rdr)
-//     }
-// }
-
-// #[derive(Debug)]
-// pub struct Reader<R> {
-//     /// The underlying reader.
-//     rdr: io::BufReader<R>,
-// }
-
-// impl<R: io::Read> Reader<R> {
-//     /// Create a new CSV reader given a builder and a source of underlying
-//     /// bytes.
-//     fn new(builder: &ReaderBuilder, rdr: R) -> Reader<R> {
-//         Reader {
-//             // core: Box::new(builder.builder.build()),
-//             rdr: io::BufReader::with_capacity(builder.capacity, rdr),
-//             // state: ReaderState {
-//             //     headers: None,
-//             //     has_headers: builder.has_headers,
-//             //     flexible: builder.flexible,
-//             //     trim: builder.trim,
-//             //     first_field_count: None,
-//             //     cur_pos: Position::new(),
-//             //     first: false,
-//             //     seeked: false,
-//             //     eof: ReaderEofState::NotEof,
-//             // },
-//         }
-//     }
-
-//     /// Create a new CSV parser with a default configuration for the given
-//     /// reader.
-//     ///
-//     /// To customize CSV parsing, use a `ReaderBuilder`.
-//     ///
-//     /// # Example
-//     ///
-//     /// ```
-//     /// use std::error::Error;
-//     /// use csv::Reader;
-//     ///
-//     /// # fn main() { example().unwrap(); }
-//     /// fn example() -> Result<(), Box<dyn Error>> {
-//     ///     let data = "\
-//     /// city,country,pop
-//     /// Boston,United States,4628910
-//     /// Concord,United States,42695
-//     /// ";
-//     ///     let mut rdr = Reader::from_reader(data.as_bytes());
-//     ///     for result in rdr.records() {
-//     ///         let record = result?;
-//     ///         println!("{:?}", record);
-//     ///     }
-//     ///     Ok(())
-//     /// }
-//     /// ```
-//     pub fn from_reader(rdr: R) -> Reader<R> {
-//         ReaderBuilder::new().from_reader(rdr)
-//     }
-
-//     /// Returns a borrowed iterator over deserialized records.
-//     ///
-//     /// Each item yielded by this iterator is a `Result<D, Error>`.
-//     /// Therefore, in order to access the record, callers must handle the
-//     /// possibility of error (typically with `try!` or `?`).
-//     ///
-//     /// If `has_headers` was enabled via a `ReaderBuilder` (which is the
-//     /// default), then this does not include the first record. Additionally,
-//     /// if `has_headers` is enabled, then deserializing into a struct will
-//     /// automatically align the values in each row to the fields of a struct
-//     /// based on the header row.
-//     ///
-//     /// # Example
-//     ///
-//     /// This shows how to deserialize CSV data into normal Rust structs. The
-//     /// fields of the header row are used to match up the values in each row
-//     /// to the fields of the struct.
-//     ///
-//     /// ```
-//     /// use std::error::Error;
-//     ///
-//     /// #[derive(Debug, serde::Deserialize, Eq, PartialEq)]
-//     /// struct Row {
-//     ///     city: String,
-//     ///     country: String,
-//     ///     #[serde(rename = "popcount")]
-//     ///     population: u64,
-//     /// }
-//     ///
-//     /// # fn main() { example().unwrap(); }
-//     /// fn example() -> Result<(), Box<dyn Error>> {
-//     ///     let data = "\
-//     /// city,country,popcount
-//     /// Boston,United States,4628910
-//     /// ";
-//     ///     let mut rdr = csv::Reader::from_reader(data.as_bytes());
-//     ///     let mut iter = rdr.deserialize();
-//     ///
-//     ///     if let Some(result) = iter.next() {
-//     ///         let record: Row = result?;
-//     ///         assert_eq!(record, Row {
-//     ///             city: "Boston".to_string(),
-//     ///             country: "United States".to_string(),
-//     ///             population: 4628910,
-//     ///         });
-//     ///         Ok(())
-//     ///     } else {
-//     ///         Err(From::from("expected at least one record but got none"))
-//     ///     }
-//     /// }
-//     /// ```
-//     ///
-//     /// # Rules
-//     ///
-//     /// For the most part, any Rust type that maps straight-forwardly to a CSV
-//     /// record is supported. This includes maps, structs, tuples and tuple
-//     /// structs. Other Rust types, such as `Vec`s, arrays, and enums have
-//     /// a more complicated story. In general, when working with CSV data, one
-//     /// should avoid *nested sequences* as much as possible.
-//     ///
-//     /// Maps, structs, tuples and tuple structs map to CSV records in a simple
-//     /// way. Tuples and tuple structs decode their fields in the order that
-//     /// they are defined. Structs will do the same only if `has_headers` has
-//     /// been disabled using [`ReaderBuilder`](struct.ReaderBuilder.html),
-//     /// otherwise, structs and maps are deserialized based on the fields
-//     /// defined in the header row. (If there is no header row, then
-//     /// deserializing into a map will result in an error.)
-//     ///
-//     /// Nested sequences are supported in a limited capacity. Namely, they
-//     /// are flattened. As a result, it's often useful to use a `Vec` to capture
-//     /// a "tail" of fields in a record:
-//     ///
-//     /// ```
-//     /// use std::error::Error;
-//     ///
-//     /// #[derive(Debug, serde::Deserialize, Eq, PartialEq)]
-//     /// struct Row {
-//     ///     label: String,
-//     ///     values: Vec<i32>,
-//     /// }
-//     ///
-//     /// # fn main() { example().unwrap(); }
-//     /// fn example() -> Result<(), Box<dyn Error>> {
-//     ///     let data = "foo,1,2,3";
-//     ///     let mut rdr = csv::ReaderBuilder::new()
-//     ///         .has_headers(false)
-//     ///         .from_reader(data.as_bytes());
-//     ///     let mut iter = rdr.deserialize();
-//     ///
-//     ///     if let Some(result) = iter.next() {
-//     ///         let record: Row = result?;
-//     ///         assert_eq!(record, Row {
-//     ///             label: "foo".to_string(),
-//     ///             values: vec![1, 2, 3],
-//     ///         });
-//     ///         Ok(())
-//     ///     } else {
-//     ///         Err(From::from("expected at least one record but got none"))
-//     ///     }
-//     /// }
-//     /// ```
-//     ///
-//     /// In the above example, adding another field to the `Row` struct after
-//     /// the `values` field will result in a deserialization error. This is
-//     /// because the deserializer doesn't know when to stop reading fields
-//     /// into the `values` vector, so it will consume the rest of the fields in
-//     /// the record leaving none left over for the additional field.
-//     ///
-//     /// Finally, simple enums in Rust can be deserialized as well. Namely,
-//     /// enums must either be variants with no arguments or variants with a
-//     /// single argument. Variants with no arguments are deserialized based on
-//     /// which variant name the field matches. Variants with a single argument
-//     /// are deserialized based on which variant can store the data. The latter
-//     /// is only supported when using "untagged" enum deserialization. The
-//     /// following example shows both forms in action:
-//     ///
-//     /// ```
-//     /// use std::error::Error;
-//     ///
-//     /// #[derive(Debug, serde::Deserialize, PartialEq)]
-//     /// struct Row {
-//     ///     label: Label,
-//     ///     value: Number,
-//     /// }
-//     ///
-//     /// #[derive(Debug, serde::Deserialize, PartialEq)]
-//     /// #[serde(rename_all = "lowercase")]
-//     /// enum Label {
-//     ///     Celsius,
-//     ///     Fahrenheit,
-//     /// }
-//     ///
-//     /// #[derive(Debug, serde::Deserialize, PartialEq)]
-//     /// #[serde(untagged)]
-//     /// enum Number {
-//     ///     Integer(i64),
-//     ///     Float(f64),
-//     /// }
-//     ///
-//     /// # fn main() { example().unwrap(); }
-//     /// fn example() -> Result<(), Box<dyn Error>> {
-//     ///     let data = "\
-//     /// label,value
-//     /// celsius,22.2222
-//     /// fahrenheit,72
-//     /// ";
-//     ///     let mut rdr = csv::Reader::from_reader(data.as_bytes());
-//     ///     let mut iter = rdr.deserialize();
-//     ///
-//     ///     // Read the first record.
-//     ///     if let Some(result) = iter.next() {
-//     ///         let record: Row = result?;
-//     ///         assert_eq!(record, Row {
-//     ///             label: Label::Celsius,
-//     ///             value: Number::Float(22.2222),
-//     ///         });
-//     ///     } else {
-//     ///         return Err(From::from(
-//     ///             "expected at least two records but got none"));
-//     ///     }
-//     ///
-//     ///     // Read the second record.
-//     ///     if let Some(result) = iter.next() {
-//     ///         let record: Row = result?;
-//     ///         assert_eq!(record, Row {
-//     ///             label: Label::Fahrenheit,
-//     ///             value: Number::Integer(72),
-//     ///         });
-//     ///         Ok(())
-//     ///     } else {
-//     ///         Err(From::from(
-//     ///             "expected at least two records but got only one"))
-//     ///     }
-//     /// }
-//     /// ```
-//     pub fn deserialize<D>(&mut self) -> DeserializeRecordsIter<R, D>
-//     where
-//         D: DeserializeOwned,
-//     {
-//         DeserializeRecordsIter::new(self)
-//     }
-// }
-
-// /// A borrowed iterator over deserialized records.
-// ///
-// /// The lifetime parameter `'r` refers to the lifetime of the underlying
-// /// CSV `Reader`. The type parameter `R` refers to the underlying `io::Read`
-// /// type, and `D` refers to the type that this iterator will deserialize a
-// /// record into.
-// pub struct DeserializeRecordsIter<'r, R: 'r, D> {
-//     rdr: &'r mut Reader<R>,
-//     obj: BytesObject,
-//     // rec: StringRecord,
-//     // headers: Option<StringRecord>,
-//     _priv: PhantomData<D>,
-// }
-
-// impl<'r, R: io::Read, D: DeserializeOwned> DeserializeRecordsIter<'r, R, D> {
-//     fn new(rdr: &'r mut Reader<R>) -> DeserializeRecordsIter<'r, R, D> {
-//         // let headers = if !rdr.state.has_headers {
-//         //     None
-//         // } else {
-//         //     rdr.headers().ok().map(Clone::clone)
-//         // };
-//         DeserializeRecordsIter {
-//             rdr,
-//             // rec: StringRecord::new(),
-//             obj: BytesObject::new(),
-//             // headers,
-//             _priv: PhantomData,
-//         }
-//     }
-
-//     /// Return a reference to the underlying CSV reader.
-//     pub fn reader(&self) -> &Reader<R> {
-//         &self.rdr
-//     }
-
-//     /// Return a mutable reference to the underlying CSV reader.
-//     pub fn reader_mut(&mut self) -> &mut Reader<R> {
-//         &mut self.rdr
-//     }
-// }
-
-// impl<'r, R: io::Read, D: DeserializeOwned> Iterator for DeserializeRecordsIter<'r, R, D> {
-//     type Item = Result<D>;
-
-//     fn next(&mut self) -> Option<Result<D>> {
-//         match self.rdr.read_object(&mut self.obj) {
-//             Err(err) => Some(Err(err)),
-//             Ok(false) => None,
-//             Ok(true) => Some(self.obj.deserialize()),
-//         }
-//     }
-// }
-// #[cfg(test)]
-// mod tests {
-
-//     use std::error::Error;
-
-//     use crate::reader::{Reader, ReaderBuilder};
-//     use serde::Deserialize;
-
-//     #[derive(Debug, Deserialize, PartialEq)]
-//     struct Row {
-//         city: String,
-//         country: String,
-//         // Serde allows us to name our headers exactly,
-//         // even if they don't match our struct field names.
-//         #[serde(rename = "popcount")]
-//         population: u64,
-//     }
-//     const JSON_DATA: &str = r#"[{"city":"Boston","country":"United States","popcount":4628910},{"city":"Concord","country":"United States","popcount":42695}]"#;
-//     #[test]
-//     fn example_json() -> Result<(), Box<dyn Error>> {
-//         let mut rdr = Reader::from_reader(JSON_DATA);
-//         let mut data = Vec::new();
-//         let mut iter = rdr.deserialize();
-//         for result in iter {
-//             let row: Row = result?;
-//             data.push(row);
-//         }
-
-//         // let data = String::from_utf8(wtr.into_inner()?)?;
-//         assert_eq!(
-//             data,
-//             vec![
-//                 Row {
-//                     city: "Boston".to_string(),
-//                     country: "United States".to_string(),
-//                     population: 4628910,
-//                 },
-//                 Row {
-//                     city: "Concord".to_string(),
-//                     country: "United States".to_string(),
-//                     population: 42695,
-//                 }
-//             ]
-//         );
-//         Ok(())
-//     }
-
-//     // #[test]
-//     // fn example_json_lines() -> Result<(), Box<dyn Error>> {
-//     //     let mut wtr = WriterBuilder::new().json_lines(true).from_writer(vec![]);
-//     //     wtr.serialize(Row {
-//     //         city: "Boston",
-//     //         country: "United States",
-//     //         population: 4628910,
-//     //     })?;
-//     //     wtr.serialize(Row {
-//     //         city: "Concord",
-//     //         country: "United States",
-//     //         population: 42695,
-//     //     })?;
-
-//     //     let data = String::from_utf8(wtr.into_inner()?)?;
-//     //     assert_eq!(
-//     //         data,
-//     //         "{\"city\":\"Boston\",\"country\":\"United States\",\"popcount\":4628910}\n{\"city\":\"Concord\",\"country\":\"United States\",\"popcount\":42695}"
-//     //     );
-//     //     Ok(())
-//     // }
-// }
+use crate::bytes_object::BytesObject;
+use crate::decoder::RecordDecoder;
+use crate::error::{Error, Limit, RecordError, Result};
+use crate::follow::Follow;
+use crate::recover::Recovery;
+use crate::reverse::ReverseReader;
+use crate::writer::Writer;
+use serde::de::DeserializeOwned;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Seek, SeekFrom},
+    marker::PhantomData,
+    ops::Range,
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+/// What a [`Reader`] does with a malformed record.
+///
+/// A record is malformed if it cannot be delimited, such as `2` in
+/// `[1 2, 3]`, or if it does not deserialize into the requested type.
+/// Errors that concern the whole document, such as a missing `[` or an I/O
+/// error, always end reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Return the error and stop reading.
+    Fail,
+    /// Skip the record and keep reading, only counting it in
+    /// [`Reader::skipped`].
+    Skip,
+    /// Skip the record and keep reading, keeping a [`RecordError`] with its
+    /// index, position and raw bytes in [`Reader::errors`].
+    Collect,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> ErrorPolicy {
+        ErrorPolicy::Fail
+    }
+}
+
+/// What a [`Reader`] accepts after the closing `]` of a top-level array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingData {
+    /// Only whitespace; anything else is a syntax error.
+    Reject,
+    /// Stop reading at the closing `]`, so whatever follows it is neither
+    /// read nor checked.
+    Ignore,
+    /// Read further arrays, such as `[1,2][3]` or one array per line, as
+    /// one sequence of records. Anything else is a syntax error.
+    Concatenated,
+}
+
+impl Default for TrailingData {
+    fn default() -> TrailingData {
+        TrailingData::Reject
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReaderBuilder {
+    pub(crate) capacity: usize,
+    is_json_lines: bool,
+    pub(crate) error_policy: ErrorPolicy,
+    max_record_bytes: Option<u64>,
+    max_depth: Option<usize>,
+    pub(crate) max_records: Option<u64>,
+    pub(crate) max_input_bytes: Option<u64>,
+    trailing_data: TrailingData,
+    skip_bom: bool,
+    require_array_end: bool,
+    recover_truncated: bool,
+    follow: bool,
+    follow_from_end: bool,
+    follow_interval: Duration,
+}
+
+impl Default for ReaderBuilder {
+    fn default() -> Self {
+        ReaderBuilder {
+            capacity: 8 * (1 << 10),
+            is_json_lines: false,
+            error_policy: ErrorPolicy::Fail,
+            max_record_bytes: None,
+            max_depth: None,
+            max_records: None,
+            max_input_bytes: None,
+            trailing_data: TrailingData::Reject,
+            skip_bom: false,
+            require_array_end: false,
+            recover_truncated: false,
+            follow: false,
+            follow_from_end: false,
+            follow_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+impl ReaderBuilder {
+    /// Create a new builder for configuring JSON reading.
+    ///
+    /// To convert a builder into a reader, call one of the methods starting
+    /// with `from_`.
+    pub fn new() -> ReaderBuilder {
+        ReaderBuilder::default()
+    }
+
+    /// Build a JSON reader from this configuration that reads data from
+    /// `rdr`.
+    ///
+    /// Note that the reader is buffered automatically, so you should not
+    /// wrap `rdr` in a buffered reader like `io::BufReader`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = "{\"city\":\"Boston\"}\n{\"city\":\"Concord\"}\n";
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .from_reader(data.as_bytes());
+    ///     for result in rdr.deserialize::<serde_json::Value>() {
+    ///         let record = result?;
+    ///         println!("{}", record["city"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_reader<R: io::Read>(&self, rdr: R) -> Reader<R> {
+        Reader::new(self, rdr)
+    }
+
+    /// Build a JSON reader from this configuration that reads data from the
+    /// given file path.
+    ///
+    /// With [`follow`](ReaderBuilder::follow), the reader keeps reading as
+    /// the file grows.
+    ///
+    /// If there was a problem opening the file at the given path, or if a
+    /// file is to be followed but this builder is not in JSON Lines mode,
+    /// then this returns the corresponding error.
+    pub fn from_path<P: AsRef<Path>>(&self, path: P) -> Result<Reader<File>> {
+        if !self.follow {
+            return Ok(Reader::new(self, File::open(path)?));
+        }
+        if !self.is_json_lines {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only JSON Lines files can be followed",
+            )));
+        }
+        let (file, follow) = Follow::open(
+            path.as_ref(),
+            self.follow_from_end,
+            self.follow_interval,
+            self.decoder(),
+        )?;
+        let mut rdr = Reader::new(self, file);
+        rdr.state.decoder.set_offset(follow.pos());
+        rdr.follow = Some(follow);
+        Ok(rdr)
+    }
+
+    /// A record decoder with the framing options of this configuration.
+    pub(crate) fn decoder(&self) -> RecordDecoder {
+        let mut decoder = RecordDecoder::new(self.is_json_lines);
+        // A malformed line in a followed file must not swallow the lines
+        // after it while they are still being written.
+        decoder.set_line_bounded(self.error_policy != ErrorPolicy::Fail || self.follow);
+        decoder.set_limits(self.max_record_bytes, self.max_depth);
+        decoder.set_document_checks(self.trailing_data, self.skip_bom, self.require_array_end);
+        decoder.set_recover_truncated(self.recover_truncated);
+        decoder
+    }
+
+    /// Build a JSON Lines reader from this configuration that reads the
+    /// records of `rdr` whose lines start inside the byte `range`.
+    ///
+    /// The reader skips the partial line at the start of the range and
+    /// reads the record that crosses its end to completion. For any
+    /// partition of the input into consecutive ranges, each record is read
+    /// by exactly one of these readers, so many workers can process one
+    /// large file without splitting it first. Positions are offsets in
+    /// `rdr`, and every other option of this builder applies as usual.
+    ///
+    /// Only JSON Lines can be read this way: the newline before a line is
+    /// the only record boundary that can be found from an arbitrary offset,
+    /// while one inside an array cannot be found without parsing from the
+    /// start. If this builder is not in JSON Lines mode, or if there was a
+    /// problem seeking or reading `rdr`, then this returns an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = "1\n22\n333\n4444\n";
+    ///     let mut builder = ReaderBuilder::new();
+    ///     builder.json_lines(true);
+    ///     let mut first = builder.from_range(Cursor::new(data), 0..4)?;
+    ///     let mut second = builder.from_range(Cursor::new(data), 4..14)?;
+    ///     let first = first.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+    ///     let second = second.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(first, vec![1, 22]);
+    ///     assert_eq!(second, vec![333, 4444]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_range<R: io::Read + Seek>(
+        &self,
+        mut rdr: R,
+        range: Range<u64>,
+    ) -> Result<Reader<R>> {
+        if !self.is_json_lines {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only JSON Lines can be read from a byte range",
+            )));
+        }
+        let start = if range.start > 0 {
+            seek_line_start(&mut rdr, range.start)?
+        } else {
+            0
+        };
+        let mut rdr = Reader::new(self, rdr);
+        rdr.state.decoder.set_offset(start);
+        rdr.start = start;
+        rdr.range_end = Some(range.end);
+        // No line starts inside the range.
+        rdr.state.done = start >= range.end;
+        Ok(rdr)
+    }
+
+    /// Build a reader from this configuration that reads the records of
+    /// `rdr` backwards, from last to first.
+    ///
+    /// The reader reads `rdr` in blocks from its end, so the latest records
+    /// of a large file can be read without scanning it from the start. Only
+    /// the [`json_lines`](ReaderBuilder::json_lines) option applies; the
+    /// other options of this builder are ignored.
+    ///
+    /// If there was a problem seeking `rdr`, then this returns the
+    /// corresponding error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = (0..10_000).map(|n| format!("{}\n", n)).collect::<String>();
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .reverse(Cursor::new(data))?;
+    ///     assert_eq!(rdr.tail::<u32>(3)?, vec![9997, 9998, 9999]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn reverse<R: io::Read + Seek>(&self, rdr: R) -> Result<ReverseReader<R>> {
+        if self.is_json_lines {
+            ReverseReader::json_lines(rdr)
+        } else {
+            ReverseReader::array(rdr)
+        }
+    }
+
+    /// Build a JSON reader from this configuration that memory-maps the
+    /// file at the given path.
+    ///
+    /// The returned [`MmapReader`](crate::mmap::MmapReader) splits records
+    /// just like a [`Reader`] built from this configuration, honouring its
+    /// error policy, limits and document checks, but the records it yields
+    /// borrow straight from the mapping, so `&str` fields without escape
+    /// sequences are deserialized without copying. Files that cannot be
+    /// mapped, such as pipes, are read into memory instead.
+    ///
+    /// If there was a problem opening, mapping or reading the file, then
+    /// this returns the corresponding error.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified, by this or any other
+    /// process, while the reader is alive. Otherwise the mapped records can
+    /// change underneath the program, which is undefined behavior, and
+    /// reading past a truncated end kills the process with `SIGBUS` on
+    /// Unix.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Event<'a> {
+    ///     kind: &'a str,
+    /// }
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     // SAFETY: nothing modifies the file while it is read.
+    ///     let rdr = unsafe {
+    ///         ReaderBuilder::new()
+    ///             .json_lines(true)
+    ///             .max_record_bytes(1 << 20)
+    ///             .from_path_mmap("events.jsonl")?
+    ///     };
+    ///     for event in rdr.deserialize::<Event>() {
+    ///         println!("{}", event?.kind);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_path_mmap<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<crate::mmap::MmapReader> {
+        crate::mmap::MmapReader::new(self, File::open(path)?)
+    }
+
+    /// Build a JSON reader from this configuration that reads zstd
+    /// compressed data from `rdr`.
+    ///
+    /// The input is decompressed as it is read, and every other option of
+    /// this builder applies as usual. Any zstd stream can be read, including
+    /// those written by
+    /// [`WriterBuilder::from_zstd_writer`](crate::WriterBuilder::from_zstd_writer)
+    /// and the seekable streams of
+    /// [`WriterBuilder::from_seekable_zstd_writer`](crate::WriterBuilder::from_seekable_zstd_writer).
+    /// To read only some records of a seekable stream, use a
+    /// [`SeekableReader`](crate::zstd::SeekableReader) instead.
+    ///
+    /// If there was a problem setting up decompression, then this returns
+    /// the corresponding error. Corrupt input is reported as an I/O error
+    /// while reading.
+    #[cfg(feature = "zstd")]
+    pub fn from_zstd_reader<R: io::Read>(
+        &self,
+        rdr: R,
+    ) -> Result<Reader<crate::zstd::ZstdReader<R>>> {
+        Ok(Reader::new(self, crate::zstd::ZstdReader::new(rdr)?))
+    }
+
+    /// Build a JSON reader from this configuration that reads zstd
+    /// compressed data from the file at the given path.
+    ///
+    /// If there was a problem opening the file at the given path, then this
+    /// returns the corresponding error.
+    #[cfg(feature = "zstd")]
+    pub fn from_zstd_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Reader<crate::zstd::ZstdReader<File>>> {
+        self.from_zstd_reader(File::open(path)?)
+    }
+
+    /// Build an asynchronous JSON reader from this configuration that reads
+    /// data from `rdr`.
+    ///
+    /// The returned [`AsyncReader`](crate::async_reader::AsyncReader) frames
+    /// records just like a [`Reader`] built from this configuration, with
+    /// the same error policy, limits and document checks. It buffers its
+    /// input, so there is no need to wrap `rdr` in a `tokio::io::BufReader`.
+    /// Dead-letter writers are not supported, since writing to one would
+    /// block the executor; use [`ErrorPolicy::Collect`] and
+    /// [`AsyncReader::take_errors`](crate::async_reader::AsyncReader::take_errors)
+    /// instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use futures_util::StreamExt;
+    /// use json_arrays::reader::ErrorPolicy;
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// async fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = "1\nx\n3\n";
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .error_policy(ErrorPolicy::Skip)
+    ///         .from_async_reader(data.as_bytes());
+    ///     let records = rdr.deserialize::<u32>().collect::<Vec<_>>().await;
+    ///     assert_eq!(records.len(), 2);
+    ///     assert_eq!(rdr.skipped(), 1);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(
+        &self,
+        rdr: R,
+    ) -> crate::async_reader::AsyncReader<R> {
+        crate::async_reader::AsyncReader::new(self, rdr)
+    }
+
+    /// Build a parallel JSON reader from this configuration that reads data
+    /// from `rdr`.
+    ///
+    /// The returned [`ParallelReader`](crate::parallel::ParallelReader)
+    /// splits the input into records just like a [`Reader`] built from this
+    /// configuration, with the same error policy, limits and document
+    /// checks, and deserializes batches of them on the rayon thread pool.
+    /// Records are still yielded in input order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::reader::ErrorPolicy;
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = (0..10_000).map(|n| format!("{}\n", n)).collect::<String>();
+    ///     let rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .error_policy(ErrorPolicy::Skip)
+    ///         .from_parallel_reader(Cursor::new(data));
+    ///     let mut expected = 0;
+    ///     for record in rdr.into_deserialize::<u32>() {
+    ///         assert_eq!(record?, expected);
+    ///         expected += 1;
+    ///     }
+    ///     assert_eq!(expected, 10_000);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn from_parallel_reader<R: io::Read>(&self, rdr: R) -> crate::parallel::ParallelReader<R> {
+        crate::parallel::ParallelReader::new(self, rdr)
+    }
+
+    /// Whether to read in json lines format.
+    ///
+    /// This is disabled by default.
+    pub fn json_lines(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.is_json_lines = yes;
+        self
+    }
+
+    /// The capacity of the buffer used for each read from the underlying
+    /// reader.
+    ///
+    /// This defaults to 8 KiB.
+    pub fn buffer_capacity(&mut self, capacity: usize) -> &mut ReaderBuilder {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// What to do with malformed records.
+    ///
+    /// With [`ErrorPolicy::Skip`] or [`ErrorPolicy::Collect`], reading
+    /// resumes after a malformed record: for JSON Lines at the next newline,
+    /// and for arrays at the next `,` outside of any string or nested value.
+    /// In JSON Lines mode, a line with an unclosed `{` or `[` then ends at
+    /// its newline instead of continuing onto the next line.
+    ///
+    /// This defaults to [`ErrorPolicy::Fail`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::reader::ErrorPolicy;
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = "1\n{\"truncated\":\n3\n";
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .error_policy(ErrorPolicy::Collect)
+    ///         .from_reader(data.as_bytes());
+    ///     let values = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(values, vec![1, 3]);
+    ///
+    ///     let errors = rdr.errors();
+    ///     assert_eq!(errors.len(), 1);
+    ///     assert_eq!(errors[0].index(), 1);
+    ///     assert_eq!(errors[0].position(), 2);
+    ///     assert_eq!(errors[0].bytes(), b"{\"truncated\":");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn error_policy(&mut self, policy: ErrorPolicy) -> &mut ReaderBuilder {
+        self.error_policy = policy;
+        self
+    }
+
+    /// The maximum size of a single record in bytes.
+    ///
+    /// Reading fails with [`Limit::RecordBytes`] as soon as a record grows
+    /// beyond this, without buffering the rest of it. Exceeding any limit
+    /// ends reading, whatever the [`ErrorPolicy`].
+    ///
+    /// There is no limit by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use json_arrays::error::{Error, Limit};
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// let data = format!("[1, \"{}\"]", "x".repeat(1 << 20));
+    /// let mut rdr = ReaderBuilder::new()
+    ///     .max_record_bytes(1024)
+    ///     .from_reader(data.as_bytes());
+    /// let results = rdr.deserialize::<serde_json::Value>().collect::<Vec<_>>();
+    /// assert!(matches!(
+    ///     results[1],
+    ///     Err(Error::LimitExceeded { limit: Limit::RecordBytes(1024), position: 4 })
+    /// ));
+    /// ```
+    pub fn max_record_bytes(&mut self, max: u64) -> &mut ReaderBuilder {
+        self.max_record_bytes = Some(max);
+        self
+    }
+
+    /// The maximum nesting depth of arrays and objects within a record.
+    ///
+    /// A record `{"a":[1]}` has a depth of 2, and a scalar record a depth
+    /// of 0. Reading fails with [`Limit::Depth`] if a record nests deeper.
+    ///
+    /// There is no limit by default.
+    pub fn max_depth(&mut self, max: usize) -> &mut ReaderBuilder {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// The maximum number of records, counting malformed ones.
+    ///
+    /// Reading fails with [`Limit::Records`] when a further record is
+    /// found.
+    ///
+    /// There is no limit by default.
+    pub fn max_records(&mut self, max: u64) -> &mut ReaderBuilder {
+        self.max_records = Some(max);
+        self
+    }
+
+    /// The maximum total size of the input in bytes.
+    ///
+    /// Reading fails with [`Limit::TotalBytes`] once more input than this
+    /// has been read.
+    ///
+    /// There is no limit by default.
+    pub fn max_input_bytes(&mut self, max: u64) -> &mut ReaderBuilder {
+        self.max_input_bytes = Some(max);
+        self
+    }
+
+    /// What may follow the closing `]` of the array.
+    ///
+    /// This defaults to [`TrailingData::Reject`], which only allows
+    /// whitespace. It has no effect on JSON Lines.
+    pub fn trailing_data(&mut self, policy: TrailingData) -> &mut ReaderBuilder {
+        self.trailing_data = policy;
+        self
+    }
+
+    /// Whether to skip a UTF-8 byte order mark at the start of the input.
+    ///
+    /// This is disabled by default, so a byte order mark is a syntax error.
+    pub fn skip_bom(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.skip_bom = yes;
+        self
+    }
+
+    /// Whether the input must end with the closing `]` of the array.
+    ///
+    /// When enabled, input that ends before the closing `]`, including
+    /// empty input, is a syntax error rather than the end of the records.
+    /// This catches uploads that were cut off between two records. It has
+    /// no effect on JSON Lines.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// let mut rdr = ReaderBuilder::new()
+    ///     .require_array_end(true)
+    ///     .from_reader(&b"[1, 2,"[..]);
+    /// let results = rdr.deserialize::<u32>().collect::<Vec<_>>();
+    /// assert_eq!(results.len(), 3);
+    /// assert!(results[2].is_err());
+    /// ```
+    pub fn require_array_end(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.require_array_end = yes;
+        self
+    }
+
+    /// Whether to read a truncated array up to its last complete element.
+    ///
+    /// A writer that dies before [`Writer::close`] leaves an array without
+    /// its closing `]`, sometimes cut off in the middle of a record. When
+    /// enabled, such a partial last record is dropped instead of causing an
+    /// error, and [`Reader::recovery`] reports what was salvaged. A number
+    /// or literal at the very end of the input is dropped as well, as it
+    /// may have been cut off. This takes precedence over
+    /// [`require_array_end`](ReaderBuilder::require_array_end) and has no
+    /// effect on JSON Lines.
+    ///
+    /// To repair the file itself, use [`recover`](crate::recover::recover).
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::ReaderBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .recover_truncated(true)
+    ///         .from_reader(&b"[{\"id\":1},{\"id\":2},{\"i"[..]);
+    ///     let records = rdr
+    ///         .deserialize::<serde_json::Value>()
+    ///         .collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(records.len(), 2);
+    ///
+    ///     let recovery = rdr.recovery().unwrap();
+    ///     assert_eq!(recovery.records(), 2);
+    ///     assert_eq!(recovery.dropped_bytes(), 4);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn recover_truncated(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.recover_truncated = yes;
+        self
+    }
+
+    /// Whether to follow a growing JSON Lines file, like `tail -f`.
+    ///
+    /// A reader built with [`from_path`](ReaderBuilder::from_path) then
+    /// does not stop at the end of the file, but waits for more records to
+    /// be appended, checking every
+    /// [`follow_interval`](ReaderBuilder::follow_interval). Its iterators
+    /// never end on their own, and
+    /// [`try_read_object`](Reader::try_read_object) returns the next record
+    /// without waiting if there is one. When the file shrinks, it is read
+    /// again from the start. On Unix, when the path starts to refer to a
+    /// different file, as happens with log rotation, the rest of the old
+    /// file is read and then the new file is read from the start. Every
+    /// other option of this builder applies as usual.
+    ///
+    /// A record is yielded as soon as it is known to be complete. Because a
+    /// JSON Lines [`Writer`] writes the newline before each record rather
+    /// than after it, this means that a trailing object, array or string is
+    /// yielded once its closing bracket or quote is written, while other
+    /// trailing values, such as numbers, are only yielded once followed by
+    /// whitespace. A line with an unclosed `{` or `[` ends at its newline,
+    /// so that a malformed line cannot swallow the lines after it.
+    ///
+    /// Only JSON Lines files can be followed. This is disabled by default
+    /// and has no effect on readers built from anything but a path.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::reader::ErrorPolicy;
+    /// use json_arrays::ReaderBuilder;
+    /// use serde_json::Value;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .follow(true)
+    ///         .follow_from_end(true)
+    ///         .error_policy(ErrorPolicy::Skip)
+    ///         .from_path("service.log.jsonl")?;
+    ///     for record in rdr.deserialize::<Value>() {
+    ///         println!("{}", record?["msg"]);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn follow(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.follow = yes;
+        self
+    }
+
+    /// Whether a followed file is read only from its current end, so that
+    /// only records appended later are read.
+    ///
+    /// This is disabled by default.
+    pub fn follow_from_end(&mut self, yes: bool) -> &mut ReaderBuilder {
+        self.follow_from_end = yes;
+        self
+    }
+
+    /// How long to wait between checks for new data in a followed file.
+    ///
+    /// This defaults to 250 milliseconds.
+    pub fn follow_interval(&mut self, interval: Duration) -> &mut ReaderBuilder {
+        self.follow_interval = interval;
+        self
+    }
+}
+
+/// A reader of JSON arrays and JSON Lines.
+#[derive(Debug)]
+pub struct Reader<R> {
+    rdr: R,
+    state: ReadState,
+    buf: Box<[u8]>,
+    /// The offset in the underlying reader at which reading started.
+    start: u64,
+    /// The end of the byte range to read records from, if any.
+    range_end: Option<u64>,
+    recover_truncated: bool,
+    /// How to follow the file being read, if it is followed.
+    follow: Option<Follow<R>>,
+}
+
+/// The decoding and error handling of a [`Reader`], apart from reading its
+/// input.
+///
+/// Readers that get their input some other way push it in here and take
+/// the records out, so every option of the [`ReaderBuilder`] applies to
+/// them in the same way.
+#[derive(Debug)]
+pub(crate) struct ReadState {
+    decoder: RecordDecoder,
+    eof: bool,
+    done: bool,
+    error_policy: ErrorPolicy,
+    /// The number of records read so far, including malformed ones.
+    index: u64,
+    /// The error being skipped past, while looking for the next record.
+    skipping: Option<Error>,
+    skipped: u64,
+    errors: Vec<RecordError>,
+    dead_letter: Option<Box<dyn DeadLetter>>,
+    max_records: Option<u64>,
+    max_input_bytes: Option<u64>,
+    /// The number of input bytes pushed so far.
+    input_bytes: u64,
+}
+
+/// A [`Writer`] of dead-letter records, whatever it writes to.
+trait DeadLetter: Send {
+    fn write(&mut self, err: &RecordError) -> Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+impl<W: io::Write + Send> DeadLetter for Writer<W> {
+    fn write(&mut self, err: &RecordError) -> Result<()> {
+        self.serialize(err)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Writer::flush(self)
+    }
+}
+
+impl fmt::Debug for dyn DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DeadLetter")
+    }
+}
+
+impl ReadState {
+    pub(crate) fn new(builder: &ReaderBuilder) -> ReadState {
+        ReadState {
+            decoder: builder.decoder(),
+            eof: false,
+            done: false,
+            error_policy: builder.error_policy,
+            index: 0,
+            skipping: None,
+            skipped: 0,
+            errors: Vec::new(),
+            dead_letter: None,
+            max_records: builder.max_records,
+            max_input_bytes: builder.max_input_bytes,
+            input_bytes: 0,
+        }
+    }
+
+    /// Append a chunk of input.
+    ///
+    /// This returns an error, and ends reading, if the input grows past the
+    /// limit set with [`ReaderBuilder::max_input_bytes`].
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        self.input_bytes += chunk.len() as u64;
+        if let Some(max) = self.max_input_bytes {
+            if self.input_bytes > max {
+                self.done = true;
+                return Err(Error::LimitExceeded {
+                    limit: Limit::TotalBytes(max),
+                    position: max,
+                });
+            }
+        }
+        self.decoder.push(chunk);
+        Ok(())
+    }
+
+    /// Signal that no more input will be pushed.
+    pub(crate) fn end_input(&mut self) {
+        self.eof = true;
+        self.decoder.end_input();
+    }
+
+    /// End reading because of an error reading the input.
+    pub(crate) fn fail<E: Into<Error>>(&mut self, err: E) -> Error {
+        self.done = true;
+        err.into()
+    }
+
+    /// Whether reading has ended, so no more input is needed.
+    pub(crate) fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Take the next record out of the input pushed so far.
+    ///
+    /// Returns `Ok(None)` if more input is needed or, once
+    /// [`is_done`](ReadState::is_done), if there are no more records.
+    /// Records that cannot be delimited are handled according to the error
+    /// policy.
+    pub(crate) fn next_object(&mut self) -> Result<Option<BytesObject>> {
+        if self.done {
+            return Ok(None);
+        }
+        loop {
+            if let Some(err) = self.skipping.take() {
+                match self.decoder.skip_invalid() {
+                    Some(obj) => {
+                        self.check_record_count(obj.position())?;
+                        self.index += 1;
+                        self.reject(self.index - 1, obj, err)?;
+                    }
+                    None => {
+                        self.skipping = Some(err);
+                        return Ok(None);
+                    }
+                }
+                continue;
+            }
+            match self.decoder.next_record() {
+                Ok(Some(obj)) => {
+                    self.check_record_count(obj.position())?;
+                    self.index += 1;
+                    return Ok(Some(obj));
+                }
+                Ok(None) if self.eof || self.decoder.is_finished() => {
+                    self.done = true;
+                    if let Some(dead_letter) = &mut self.dead_letter {
+                        dead_letter.flush()?;
+                    }
+                    return Ok(None);
+                }
+                Ok(None) => return Ok(None),
+                Err(err)
+                    if self.error_policy != ErrorPolicy::Fail
+                        && self.decoder.can_recover()
+                        && !matches!(err, Error::LimitExceeded { .. }) =>
+                {
+                    self.skipping = Some(err);
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Deserialize a record returned by
+    /// [`next_object`](ReadState::next_object).
+    ///
+    /// Returns `Ok(None)` if the record is malformed and skipped according
+    /// to the error policy.
+    pub(crate) fn deserialize<D: DeserializeOwned>(
+        &mut self,
+        obj: BytesObject,
+    ) -> Result<Option<D>> {
+        let result = obj.deserialize();
+        match result {
+            Ok(record) => Ok(Some(record)),
+            Err(err) => self
+                .reject_deserialized(self.index - 1, obj, err)
+                .map(|()| None),
+        }
+    }
+
+    /// Handle the record with the given index, which failed to deserialize,
+    /// according to the error policy.
+    ///
+    /// Returns the error if it ends reading.
+    pub(crate) fn reject_deserialized(
+        &mut self,
+        index: u64,
+        obj: BytesObject,
+        err: Error,
+    ) -> Result<()> {
+        if self.error_policy == ErrorPolicy::Fail {
+            self.done = true;
+            return Err(err);
+        }
+        self.reject(index, obj, err)
+    }
+
+    pub(crate) fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    pub(crate) fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub(crate) fn errors(&self) -> &[RecordError] {
+        &self.errors
+    }
+
+    pub(crate) fn take_errors(&mut self) -> Vec<RecordError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn reject(&mut self, index: u64, obj: BytesObject, error: Error) -> Result<()> {
+        self.skipped += 1;
+        let position = obj.position();
+        let err = RecordError {
+            index,
+            position,
+            bytes: obj.into_bytes(),
+            error,
+        };
+        if let Some(dead_letter) = &mut self.dead_letter {
+            if let Err(err) = dead_letter.write(&err) {
+                self.done = true;
+                return Err(err);
+            }
+        }
+        if self.error_policy == ErrorPolicy::Collect {
+            self.errors.push(err);
+        }
+        Ok(())
+    }
+
+    fn check_record_count(&mut self, position: u64) -> Result<()> {
+        match self.max_records {
+            Some(max) if self.index >= max => {
+                self.done = true;
+                Err(Error::LimitExceeded {
+                    limit: Limit::Records(max),
+                    position,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Reader<File> {
+    /// Create a new JSON array reader with a default configuration for the
+    /// file at the given path.
+    ///
+    /// If there was a problem opening the file at the given path, then this
+    /// returns the corresponding error.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<File>> {
+        ReaderBuilder::new().from_path(path)
+    }
+}
+
+impl<R: io::Read> Reader<R> {
+    fn new(builder: &ReaderBuilder, rdr: R) -> Reader<R> {
+        Reader {
+            rdr,
+            state: ReadState::new(builder),
+            buf: vec![0; builder.capacity].into_boxed_slice(),
+            start: 0,
+            range_end: None,
+            recover_truncated: builder.recover_truncated,
+            follow: None,
+        }
+    }
+
+    /// Create a new JSON array reader with a default configuration for the
+    /// given reader.
+    ///
+    /// To read JSON Lines or customize reading, use a [`ReaderBuilder`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::Reader;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Row {
+    ///     city: String,
+    ///     #[serde(rename = "popcount")]
+    ///     population: u64,
+    /// }
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = r#"[{"city":"Boston","popcount":4628910}]"#;
+    ///     let mut rdr = Reader::from_reader(data.as_bytes());
+    ///     let rows = rdr.deserialize::<Row>().collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(rows, vec![Row {
+    ///         city: "Boston".to_string(),
+    ///         population: 4628910,
+    ///     }]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_reader(rdr: R) -> Reader<R> {
+        ReaderBuilder::new().from_reader(rdr)
+    }
+
+    /// Returns a borrowed iterator over deserialized records.
+    ///
+    /// Each item yielded by this iterator is a `Result<D, Error>`. With the
+    /// default [`ErrorPolicy::Fail`], the iterator ends after the first
+    /// error; otherwise malformed records are skipped and only errors that
+    /// end reading are yielded.
+    pub fn deserialize<D: DeserializeOwned>(&mut self) -> DeserializeRecordsIter<'_, R, D> {
+        DeserializeRecordsIter {
+            rdr: self,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Returns an owned iterator over deserialized records.
+    ///
+    /// This is like [`deserialize`](Reader::deserialize), but the iterator
+    /// takes ownership of the reader.
+    pub fn into_deserialize<D: DeserializeOwned>(self) -> DeserializeRecordsIntoIter<R, D> {
+        DeserializeRecordsIntoIter {
+            rdr: self,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Read the raw bytes of the next record.
+    ///
+    /// Records that cannot be delimited are handled according to the
+    /// reader's [`ErrorPolicy`]. Records are not deserialized here, so they
+    /// may still hold invalid JSON.
+    ///
+    /// For a file being [followed](ReaderBuilder::follow), this waits until
+    /// a record is appended.
+    pub fn read_object(&mut self) -> Result<Option<BytesObject>> {
+        loop {
+            if let Some(obj) = self.try_read_object()? {
+                return Ok(Some(obj));
+            }
+            match self.follow {
+                Some(ref follow) if !self.state.is_done() => thread::sleep(follow.poll_interval()),
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    /// Read the raw bytes of the next record without waiting for a file
+    /// being [followed](ReaderBuilder::follow) to grow.
+    ///
+    /// Returns `None` if the followed file holds no further complete
+    /// record yet. Otherwise, this is the same as
+    /// [`read_object`](Reader::read_object).
+    pub fn try_read_object(&mut self) -> Result<Option<BytesObject>> {
+        loop {
+            if let Some(obj) = self.state.next_object()? {
+                return Ok(Some(obj));
+            }
+            if self.state.is_done() || !self.fill_buf()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// What was salvaged from a truncated array with
+    /// [`recover_truncated`](ReaderBuilder::recover_truncated).
+    ///
+    /// Returns `None` until the end of the input is reached, or if the array
+    /// was complete.
+    pub fn recovery(&self) -> Option<Recovery> {
+        if !self.state.done {
+            return None;
+        }
+        let keep = self.truncated_at()?;
+        Some(Recovery {
+            records: self.state.index,
+            dropped_bytes: self.state.input_bytes - keep,
+        })
+    }
+
+    /// The offset just past the last complete element of a truncated
+    /// array, once the end of the input is reached.
+    pub(crate) fn truncated_at(&self) -> Option<u64> {
+        if !self.recover_truncated {
+            return None;
+        }
+        self.state.decoder.truncated_at()
+    }
+
+    /// The policy for malformed records.
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.state.error_policy()
+    }
+
+    /// The number of malformed records skipped so far.
+    pub fn skipped(&self) -> u64 {
+        self.state.skipped()
+    }
+
+    /// The malformed records skipped so far with [`ErrorPolicy::Collect`].
+    pub fn errors(&self) -> &[RecordError] {
+        self.state.errors()
+    }
+
+    /// Take the malformed records collected so far, leaving none behind.
+    pub fn take_errors(&mut self) -> Vec<RecordError> {
+        self.state.take_errors()
+    }
+
+    /// Write every malformed record that is skipped from now on to `wtr`.
+    ///
+    /// Each record is serialized as a [`RecordError`], with its index,
+    /// source position, an error description and its raw bytes, so it can
+    /// be traced back, inspected and replayed later. The writer is flushed
+    /// when the end of the input is reached and closed when the reader is
+    /// dropped.
+    ///
+    /// The writer must be in JSON Lines mode, so that the rejected records
+    /// can be read back one by one even if reading ends early. If it is
+    /// not, then this returns an error and leaves the reader unchanged.
+    ///
+    /// Records are only skipped with [`ErrorPolicy::Skip`] or
+    /// [`ErrorPolicy::Collect`]. An error writing to `wtr` ends reading.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::reader::ErrorPolicy;
+    /// use json_arrays::{ReaderBuilder, WriterBuilder};
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .error_policy(ErrorPolicy::Skip)
+    ///         .from_path("events.jsonl")?;
+    ///     rdr.set_dead_letter(WriterBuilder::new().json_lines(true).from_path("rejects.jsonl")?)?;
+    ///     for result in rdr.deserialize::<serde_json::Value>() {
+    ///         println!("{}", result?);
+    ///     }
+    ///     println!("{} records rejected", rdr.skipped());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_dead_letter<W: io::Write + Send + 'static>(&mut self, wtr: Writer<W>) -> Result<()> {
+        if !wtr.is_json_lines() {
+            return Err(Error::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the dead-letter writer must write JSON Lines",
+            )));
+        }
+        self.state.dead_letter = Some(Box::new(wtr));
+        Ok(())
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Consume the reader and return the underlying reader.
+    ///
+    /// Any input that was buffered but not yet read as records is lost.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    fn next_deserialized<D: DeserializeOwned>(&mut self) -> Option<Result<D>> {
+        loop {
+            let obj = match self.read_object() {
+                Ok(Some(obj)) => obj,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            match self.state.deserialize(obj) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// The number of records read so far, including malformed ones.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn index(&self) -> u64 {
+        self.state.index
+    }
+
+    /// Handle the record with the given index, which failed to deserialize
+    /// elsewhere, according to the error policy.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn reject_deserialized(
+        &mut self,
+        index: u64,
+        obj: BytesObject,
+        err: Error,
+    ) -> Result<()> {
+        self.state.reject_deserialized(index, obj, err)
+    }
+
+    /// Read the next chunk of input into the decoder.
+    ///
+    /// Returns `false` if a followed file has no new data yet.
+    fn fill_buf(&mut self) -> Result<bool> {
+        let n = loop {
+            match self.rdr.read(&mut self.buf) {
+                Ok(n) => break n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(self.state.fail(err)),
+            }
+        };
+        if n == 0 {
+            let follow = match self.follow {
+                Some(ref mut follow) => follow,
+                None => {
+                    self.state.end_input();
+                    return Ok(true);
+                }
+            };
+            return match follow.reopen_if_replaced() {
+                Ok(Some((rdr, decoder))) => {
+                    // Whatever is left of an incomplete record in the old
+                    // file is dropped.
+                    self.rdr = rdr;
+                    self.state.decoder = decoder;
+                    self.state.skipping = None;
+                    Ok(true)
+                }
+                Ok(None) => Ok(false),
+                Err(err) => Err(self.state.fail(err)),
+            };
+        }
+        if let Some(ref mut follow) = self.follow {
+            follow.advance(n);
+        }
+        let offset = self.start + self.state.input_bytes;
+        let mut chunk = &self.buf[..n];
+        let mut last = false;
+        if let Some(end) = self.range_end {
+            // The last line starting in the range ends at the first newline
+            // from `end - 1` on, and nothing after it is read.
+            let from = (end - 1).saturating_sub(offset).min(n as u64) as usize;
+            if let Some(i) = chunk[from..].iter().position(|&b| b == b'\n') {
+                chunk = &chunk[..from + i + 1];
+                last = true;
+            }
+        }
+        self.state.push(chunk)?;
+        if last {
+            self.state.end_input();
+        }
+        Ok(true)
+    }
+}
+
+/// Seek `rdr` to the first line that starts at or after `offset`, which
+/// must not be 0, and return its position.
+fn seek_line_start<R: io::Read + Seek>(rdr: &mut R, offset: u64) -> io::Result<u64> {
+    // A line starts at `offset` exactly when the byte before it is a
+    // newline, so skip through the first newline from there.
+    let mut pos = rdr.seek(SeekFrom::Start(offset - 1))?;
+    let mut buf = [0; 1024];
+    loop {
+        let n = match rdr.read(&mut buf) {
+            Ok(0) => return Ok(pos),
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if let Some(i) = buf[..n].iter().position(|&b| b == b'\n') {
+            return rdr.seek(SeekFrom::Start(pos + i as u64 + 1));
+        }
+        pos += n as u64;
+    }
+}
+
+/// A borrowed iterator over deserialized records.
+///
+/// The lifetime parameter `'r` refers to the lifetime of the underlying
+/// [`Reader`].
+#[derive(Debug)]
+pub struct DeserializeRecordsIter<'r, R, D> {
+    rdr: &'r mut Reader<R>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<'r, R: io::Read, D: DeserializeOwned> DeserializeRecordsIter<'r, R, D> {
+    /// Return a reference to the underlying reader.
+    pub fn reader(&self) -> &Reader<R> {
+        self.rdr
+    }
+
+    /// Return a mutable reference to the underlying reader.
+    pub fn reader_mut(&mut self) -> &mut Reader<R> {
+        self.rdr
+    }
+}
+
+impl<'r, R: io::Read, D: DeserializeOwned> Iterator for DeserializeRecordsIter<'r, R, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        self.rdr.next_deserialized()
+    }
+}
+
+/// An owned iterator over deserialized records.
+#[derive(Debug)]
+pub struct DeserializeRecordsIntoIter<R, D> {
+    rdr: Reader<R>,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<R: io::Read, D: DeserializeOwned> DeserializeRecordsIntoIter<R, D> {
+    /// Return a reference to the underlying reader.
+    pub fn reader(&self) -> &Reader<R> {
+        &self.rdr
+    }
+
+    /// Return the underlying reader.
+    pub fn into_reader(self) -> Reader<R> {
+        self.rdr
+    }
+}
+
+impl<R: io::Read, D: DeserializeOwned> Iterator for DeserializeRecordsIntoIter<R, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        self.rdr.next_deserialized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::{self, Cursor, Write};
+    use std::sync::{Arc, Mutex};
+
+    use crate::error::Error as JsonError;
+    use crate::reader::{ErrorPolicy, Reader, ReaderBuilder, TrailingData};
+    use crate::WriterBuilder;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        city: String,
+        country: String,
+        // Serde allows us to name our headers exactly,
+        // even if they don't match our struct field names.
+        #[serde(rename = "popcount")]
+        population: u64,
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {
+                city: "Boston".to_string(),
+                country: "United States".to_string(),
+                population: 4628910,
+            },
+            Row {
+                city: "Concord".to_string(),
+                country: "United States".to_string(),
+                population: 42695,
+            },
+        ]
+    }
+
+    const JSON_DATA: &str = r#"[{"city":"Boston","country":"United States","popcount":4628910},{"city":"Concord","country":"United States","popcount":42695}]"#;
+    const JSON_LINES_DATA: &str = "{\"city\":\"Boston\",\"country\":\"United States\",\"popcount\":4628910}\n{\"city\":\"Concord\",\"country\":\"United States\",\"popcount\":42695}\n";
+
+    #[test]
+    fn example_json() -> Result<(), Box<dyn Error>> {
+        let mut rdr = Reader::from_reader(JSON_DATA.as_bytes());
+        let mut data = Vec::new();
+        for result in rdr.deserialize() {
+            let row: Row = result?;
+            data.push(row);
+        }
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[test]
+    fn example_json_lines() -> Result<(), Box<dyn Error>> {
+        let data = ReaderBuilder::new()
+            .json_lines(true)
+            .buffer_capacity(5)
+            .from_reader(JSON_LINES_DATA.as_bytes())
+            .into_deserialize::<Row>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(data, rows());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_first_error_by_default() {
+        let mut rdr = Reader::from_reader(&b"[1, \"x\", 3]"[..]);
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn skips_malformed_json_lines() -> Result<(), Box<dyn Error>> {
+        let data = "1\nnope\n{\"a\":[\n4\n,\n\"six\"\n7";
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .error_policy(ErrorPolicy::Skip)
+            .buffer_capacity(3)
+            .from_reader(data.as_bytes());
+        let values = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 4, 7]);
+        assert_eq!(rdr.skipped(), 4);
+        assert!(rdr.errors().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn collects_malformed_array_elements() -> Result<(), Box<dyn Error>> {
+        let data = r#"[1, "two", 3 {"x": "a,]"}, [4,5] , , 6 7]"#;
+        let mut rdr = ReaderBuilder::new()
+            .error_policy(ErrorPolicy::Collect)
+            .from_reader(data.as_bytes());
+        let values = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 3, 6]);
+        let errors = rdr
+            .errors()
+            .iter()
+            .map(|err| {
+                (
+                    err.index(),
+                    err.position(),
+                    String::from_utf8(err.bytes().to_vec()).unwrap(),
+                    matches!(err.error(), JsonError::SyntaxError { .. }),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (1, 4, r#""two""#.to_string(), false),
+                (3, 13, r#"{"x": "a,]"}"#.to_string(), true),
+                (4, 27, "[4,5]".to_string(), false),
+                (5, 35, "".to_string(), true),
+                (7, 39, "7".to_string(), true),
+            ]
+        );
+        assert_eq!(rdr.take_errors().len(), 5);
+        assert!(rdr.errors().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn document_errors_end_reading() {
+        let mut rdr = ReaderBuilder::new()
+            .error_policy(ErrorPolicy::Skip)
+            .from_reader(&b"{\"a\":1}"[..]);
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(JsonError::SyntaxError { .. })));
+    }
+
+    /// A writer whose output can still be read after it is moved.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_rejects_to_dead_letter() -> Result<(), Box<dyn Error>> {
+        let rejects = SharedBuf::default();
+        let mut rdr = ReaderBuilder::new()
+            .error_policy(ErrorPolicy::Skip)
+            .from_reader(&b"[1, \"two\", 3 x, \xff4, 5]"[..]);
+        assert!(rdr
+            .set_dead_letter(WriterBuilder::new().from_writer(Vec::new()))
+            .is_err());
+        rdr.set_dead_letter(
+            WriterBuilder::new()
+                .json_lines(true)
+                .from_writer(rejects.clone()),
+        )?;
+        let values = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, vec![1, 3, 5]);
+
+        let output = rejects.0.lock().unwrap().clone();
+        let mut lines = Vec::new();
+        for line in output.split(|&b| b == b'\n') {
+            let mut line: Value = serde_json::from_slice(line)?;
+            assert!(!line["error"].as_str().unwrap().is_empty());
+            line.as_object_mut().unwrap().remove("error");
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                json!({"index": 1, "position": 4, "record": "\"two\""}),
+                json!({"index": 3, "position": 13, "record": "x"}),
+                json!({"index": 4, "position": 16, "record_bytes": [0xff, b'4']}),
+            ]
+        );
+        // The raw record can be replayed.
+        let replayed: String = serde_json::from_str(lines[0]["record"].as_str().unwrap())?;
+        assert_eq!(replayed, "two");
+        Ok(())
+    }
+
+    #[test]
+    fn enforces_limits() {
+        use crate::error::Limit;
+
+        fn first_error(builder: &mut ReaderBuilder, data: &str) -> Option<(Limit, u64)> {
+            let mut rdr = builder.buffer_capacity(4).from_reader(data.as_bytes());
+            rdr.deserialize::<Value>().find_map(|result| match result {
+                Err(JsonError::LimitExceeded { limit, position }) => Some((limit, position)),
+                _ => None,
+            })
+        }
+
+        let data = r#"[1, [2, {"a": "]]]]"}], "xxxxxxxxxx", 4]"#;
+        assert_eq!(first_error(&mut ReaderBuilder::new(), data), None);
+        assert_eq!(
+            first_error(ReaderBuilder::new().max_record_bytes(17), data),
+            Some((Limit::RecordBytes(17), 4))
+        );
+        assert_eq!(
+            first_error(ReaderBuilder::new().max_record_bytes(18), data),
+            None
+        );
+        assert_eq!(
+            first_error(ReaderBuilder::new().max_depth(1), data),
+            Some((Limit::Depth(1), 4))
+        );
+        assert_eq!(first_error(ReaderBuilder::new().max_depth(2), data), None);
+        assert_eq!(
+            first_error(ReaderBuilder::new().max_records(3), data),
+            Some((Limit::Records(3), 38))
+        );
+        assert_eq!(
+            first_error(ReaderBuilder::new().max_input_bytes(30), data),
+            Some((Limit::TotalBytes(30), 30))
+        );
+        // Limits end reading even when malformed records are skipped.
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .error_policy(ErrorPolicy::Skip)
+            .max_record_bytes(3)
+            .from_reader(&b"1\n\"long\"\n2\n"[..]);
+        let results = rdr.deserialize::<u32>().collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn checks_document_structure() {
+        fn read(builder: &mut ReaderBuilder, data: &[u8]) -> Result<Vec<u32>, String> {
+            builder
+                .buffer_capacity(2)
+                .from_reader(data)
+                .into_deserialize::<u32>()
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| err.to_string())
+        }
+
+        assert_eq!(read(&mut ReaderBuilder::new(), b"[1,2] \n"), Ok(vec![1, 2]));
+        assert!(read(&mut ReaderBuilder::new(), b"[1,2] x").is_err());
+        assert!(read(&mut ReaderBuilder::new(), b"[1,2][3]").is_err());
+        assert_eq!(
+            read(
+                ReaderBuilder::new().trailing_data(TrailingData::Ignore),
+                b"[1,2] ]x[,"
+            ),
+            Ok(vec![1, 2])
+        );
+        struct Broken;
+        impl io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "read past the array"))
+            }
+        }
+        let mut rdr = ReaderBuilder::new()
+            .trailing_data(TrailingData::Ignore)
+            .buffer_capacity(2)
+            .from_reader(io::Read::chain(&b"[1,2] "[..], Broken));
+        let records: Vec<u32> = rdr.deserialize().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records, [1, 2]);
+        assert!(rdr.read_object().unwrap().is_none());
+        assert_eq!(
+            read(
+                ReaderBuilder::new().trailing_data(TrailingData::Concatenated),
+                b"[1,2]\n[3] []\n"
+            ),
+            Ok(vec![1, 2, 3])
+        );
+        assert!(read(
+            ReaderBuilder::new().trailing_data(TrailingData::Concatenated),
+            b"[1,2] 3"
+        )
+        .is_err());
+
+        let bom = b"\xEF\xBB\xBF[1]";
+        assert_eq!(
+            read(&mut ReaderBuilder::new(), bom),
+            Err("syntax error at byte 0: unexpected UTF-8 byte order mark".to_string())
+        );
+        assert_eq!(read(ReaderBuilder::new().skip_bom(true), bom), Ok(vec![1]));
+        assert_eq!(
+            read(
+                ReaderBuilder::new().json_lines(true).skip_bom(true),
+                b"\xEF\xBB\xBF1\n2"
+            ),
+            Ok(vec![1, 2])
+        );
+
+        assert_eq!(read(&mut ReaderBuilder::new(), b"[1,2"), Ok(vec![1, 2]));
+        for truncated in [&b"[1,2"[..], b"[1,2,", b"[", b""] {
+            assert!(
+                read(
+                    ReaderBuilder::new()
+                        .require_array_end(true)
+                        .error_policy(ErrorPolicy::Skip),
+                    truncated
+                )
+                .is_err(),
+                "{:?}",
+                truncated
+            );
+        }
+        assert_eq!(
+            read(ReaderBuilder::new().require_array_end(true), b" [ ] "),
+            Ok(vec![])
+        );
+    }
+
+    fn sharded_data() -> String {
+        let mut data = String::new();
+        for n in 0..200u32 {
+            data.push_str(&"7".repeat(1 + (n as usize * 7) % 13));
+            data.push('\n');
+            if n % 17 == 0 {
+                data.push_str("\n  \r\n");
+            }
+        }
+        data
+    }
+
+    fn read_range(data: &str, range: std::ops::Range<u64>) -> Result<Vec<u64>, Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .buffer_capacity(3)
+            .from_range(Cursor::new(data), range)?;
+        Ok(rdr.deserialize::<u64>().collect::<Result<Vec<_>, _>>()?)
+    }
+
+    #[test]
+    fn range_partitions_read_every_record_once() -> Result<(), Box<dyn Error>> {
+        let data = sharded_data();
+        let expected = read_range(&data, 0..u64::MAX)?;
+        assert_eq!(expected.len(), 200);
+        let len = data.len() as u64;
+        for shard_len in [1, 2, 3, 5, 16, 100, len - 1, len] {
+            let mut records = Vec::new();
+            let mut start = 0;
+            while start < len {
+                let end = (start + shard_len).min(len);
+                records.extend(read_range(&data, start..end)?);
+                start = end;
+            }
+            assert_eq!(records, expected, "shard length {}", shard_len);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn range_reports_positions_in_the_input() -> Result<(), Box<dyn Error>> {
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_range(Cursor::new("1\n 22 }\n333"), 1..10)?;
+        let obj = rdr.read_object()?.unwrap();
+        assert_eq!((obj.as_bytes(), obj.position()), (&b"22"[..], 3));
+        let err = rdr.read_object().unwrap_err();
+        assert!(matches!(err, JsonError::SyntaxError { position: 6, .. }));
+
+        let mut rdr = ReaderBuilder::new()
+            .json_lines(true)
+            .from_range(Cursor::new("1\n2\n"), 2..2)?;
+        assert!(rdr.read_object()?.is_none());
+
+        assert!(ReaderBuilder::new()
+            .from_range(Cursor::new("[1]"), 0..3)
+            .is_err());
+        Ok(())
+    }
+}
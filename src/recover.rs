@@ -0,0 +1,146 @@
+//! Repairing JSON array files left truncated by a crashed writer.
+//!
+//! A [`Writer`](crate::Writer) only writes the closing `]` of an array when
+//! it is closed, so a process that dies while writing leaves a file that is
+//! not valid JSON, possibly ending in the middle of a record. [`recover`]
+//! cuts such a file back to its last complete element and closes the array.
+use crate::error::Result;
+use crate::reader::ReaderBuilder;
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// What was salvaged from a truncated array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovery {
+    pub(crate) records: u64,
+    pub(crate) dropped_bytes: u64,
+}
+
+impl Recovery {
+    /// The number of complete records that were kept.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// The number of bytes dropped from the end of the input.
+    pub fn dropped_bytes(&self) -> u64 {
+        self.dropped_bytes
+    }
+}
+
+/// Repair the JSON array file at the given path in place.
+///
+/// The file is read up to its last complete element. If the closing `]` is
+/// missing, the file is truncated after that element and `]` is appended,
+/// so it holds a valid array of the salvaged records; an empty file becomes
+/// `[]`. A file that is already complete is left as it is. As with
+/// [`ReaderBuilder::recover_truncated`], a number or literal at the very
+/// end of the file is dropped, since it may have been cut off.
+///
+/// If the file is not a JSON array, or a complete record in it is
+/// malformed, then this returns an error and the file is not modified.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::error::Error;
+///
+/// use json_arrays::recover::recover;
+///
+/// # fn main() { example().unwrap(); }
+/// fn example() -> Result<(), Box<dyn Error>> {
+///     let recovery = recover("events.json")?;
+///     println!(
+///         "salvaged {} records, dropped {} bytes",
+///         recovery.records(),
+///         recovery.dropped_bytes()
+///     );
+///     Ok(())
+/// }
+/// ```
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<Recovery> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut rdr = ReaderBuilder::new()
+        .recover_truncated(true)
+        .from_reader(&mut file);
+    for record in rdr.deserialize::<serde::de::IgnoredAny>() {
+        record?;
+    }
+    let (recovery, keep) = match (rdr.recovery(), rdr.truncated_at()) {
+        (Some(recovery), Some(keep)) => (recovery, keep),
+        _ => {
+            return Ok(Recovery {
+                records: count_records(&mut file)?,
+                dropped_bytes: 0,
+            })
+        }
+    };
+    file.set_len(keep)?;
+    file.seek(SeekFrom::Start(keep))?;
+    file.write_all(if keep == 0 { b"[]" } else { b"]" })?;
+    file.sync_all()?;
+    Ok(recovery)
+}
+
+/// Count the records of the complete array in `file`.
+fn count_records(file: &mut std::fs::File) -> Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut rdr = ReaderBuilder::new().from_reader(file);
+    let mut records = 0;
+    while rdr.read_object()?.is_some() {
+        records += 1;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::fs;
+
+    use crate::recover::recover;
+    use crate::test_util::TempPath;
+    use crate::WriterBuilder;
+
+    #[test]
+    fn repairs_truncated_arrays() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new().from_writer(vec![]);
+        for n in 0..3 {
+            wtr.serialize(serde_json::json!({"n": n, "s": "a,]}\""}))?;
+        }
+        let full = wtr.into_inner()?;
+        // Cut the output off at every byte before the closing `]`.
+        for len in 0..full.len() {
+            let file = TempPath::with_contents("cut.json", &full[..len]);
+            let recovery = recover(&file)?;
+            let repaired = fs::read(&file)?;
+            let keep = len - recovery.dropped_bytes() as usize;
+            if keep == 0 {
+                assert_eq!(repaired, b"[]");
+            } else {
+                assert_eq!(repaired, [&full[..keep], b"]"].concat(), "{}", len);
+            }
+            let records: Vec<serde_json::Value> = serde_json::from_slice(&repaired)?;
+            assert_eq!(records.len() as u64, recovery.records(), "{}", len);
+            assert_eq!(recover(&file)?.dropped_bytes(), 0);
+        }
+
+        let file = TempPath::with_contents("numbers.json", b"[1, 22, 333");
+        let recovery = recover(&file)?;
+        assert_eq!((recovery.records(), recovery.dropped_bytes()), (2, 5));
+        assert_eq!(fs::read(&file)?, b"[1, 22]");
+
+        let file = TempPath::with_contents("complete.json", b"[1,2]\n");
+        assert_eq!(recover(&file)?.records(), 2);
+        assert_eq!(fs::read(&file)?, b"[1,2]\n");
+
+        let file = TempPath::with_contents("invalid.json", b"[1, {\"a\" 2}, 3");
+        assert!(recover(&file).is_err());
+        assert_eq!(fs::read(&file)?, b"[1, {\"a\" 2}, 3");
+        Ok(())
+    }
+}
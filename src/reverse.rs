@@ -0,0 +1,362 @@
+//! Reading records backwards from the end of a file.
+//!
+//! A [`ReverseReader`] reads a seekable JSON Lines or JSON array file in
+//! blocks from the end, so the latest records of a large file can be read
+//! without scanning it from the start. Build one with
+//! [`ReaderBuilder::reverse`](crate::ReaderBuilder::reverse), or with
+//! [`ReverseReader::array`] or [`ReverseReader::json_lines`].
+use crate::bytes_object::BytesObject;
+use crate::decoder::is_whitespace;
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use std::{
+    io::{self, Seek, SeekFrom},
+    marker::PhantomData,
+};
+
+/// The amount of data read from the file at a time.
+const BLOCK_SIZE: u64 = 64 * (1 << 10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReverseState {
+    ArrayEnd,
+    Elements,
+    Line,
+    Done,
+}
+
+/// A reader that yields records from last to first.
+///
+/// For arrays, the reader finds the closing `]` and walks the elements
+/// backwards, keeping track of strings and nesting so that commas inside
+/// records are not mistaken for separators.
+#[derive(Debug)]
+pub struct ReverseReader<R> {
+    rdr: R,
+    /// The data in the file from `buf_start` up to the end of the unread
+    /// part.
+    buf: Vec<u8>,
+    buf_start: u64,
+    /// Everything at or after this offset has been read.
+    end: u64,
+    state: ReverseState,
+}
+
+impl<R: io::Read + Seek> ReverseReader<R> {
+    /// Create a reader that reads the JSON array in `rdr` backwards.
+    pub fn array(rdr: R) -> Result<ReverseReader<R>> {
+        ReverseReader::new(rdr, ReverseState::ArrayEnd)
+    }
+
+    /// Create a reader that reads the JSON Lines in `rdr` backwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::reverse::ReverseReader;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = (0..10_000).map(|n| format!("{}\n", n)).collect::<String>();
+    ///     let mut rdr = ReverseReader::json_lines(Cursor::new(data))?;
+    ///     assert_eq!(rdr.tail::<u32>(3)?, vec![9997, 9998, 9999]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn json_lines(rdr: R) -> Result<ReverseReader<R>> {
+        ReverseReader::new(rdr, ReverseState::Line)
+    }
+
+    fn new(mut rdr: R, state: ReverseState) -> Result<ReverseReader<R>> {
+        let end = rdr.seek(SeekFrom::End(0))?;
+        Ok(ReverseReader {
+            rdr,
+            buf: Vec::new(),
+            buf_start: end,
+            end,
+            state,
+        })
+    }
+
+    /// Read the raw bytes of the previous record.
+    pub fn read_object(&mut self) -> Result<Option<BytesObject>> {
+        let result = match self.state {
+            ReverseState::Done => return Ok(None),
+            ReverseState::Line => self.previous_line(),
+            ReverseState::ArrayEnd | ReverseState::Elements => self.previous_element(),
+        };
+        match result {
+            Ok(Some(obj)) => {
+                // Nothing after the record is needed any more.
+                let keep = (obj.position() - self.buf_start) as usize;
+                self.buf.truncate(keep);
+                Ok(Some(obj))
+            }
+            Ok(None) => {
+                self.state = ReverseState::Done;
+                Ok(None)
+            }
+            Err(err) => {
+                self.state = ReverseState::Done;
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns a borrowed iterator over deserialized records from last to
+    /// first.
+    ///
+    /// Each item yielded by this iterator is a `Result<D, Error>`. The
+    /// iterator ends after the first error.
+    pub fn deserialize<D: DeserializeOwned>(&mut self) -> DeserializeReverseIter<'_, R, D> {
+        DeserializeReverseIter {
+            rdr: self,
+            done: false,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Deserialize the last `n` records, in their order in the file.
+    pub fn tail<D: DeserializeOwned>(&mut self, n: usize) -> Result<Vec<D>> {
+        let mut records = self.deserialize().take(n).collect::<Result<Vec<D>>>()?;
+        records.reverse();
+        Ok(records)
+    }
+
+    fn previous_line(&mut self) -> Result<Option<BytesObject>> {
+        let end = self.skip_whitespace(self.end)?;
+        if end == 0 {
+            return Ok(None);
+        }
+        let mut start = end;
+        while start > 0 && self.byte(start - 1)? != b'\n' {
+            start -= 1;
+        }
+        let start = self.skip_whitespace_forward(start, end)?;
+        self.end = start;
+        self.object(start, end).map(Some)
+    }
+
+    fn previous_element(&mut self) -> Result<Option<BytesObject>> {
+        let mut pos = self.skip_whitespace(self.end)?;
+        if self.state == ReverseState::ArrayEnd {
+            if pos == 0 || self.byte(pos - 1)? != b']' {
+                return Err(syntax_error(pos, "expected `]`"));
+            }
+            pos = self.skip_whitespace(pos - 1)?;
+            if pos > 0 && self.byte(pos - 1)? == b'[' {
+                return self.array_start(pos - 1).map(|()| None);
+            }
+            self.state = ReverseState::Elements;
+        }
+        let end = pos;
+        let mut depth = 0usize;
+        let separator = loop {
+            if pos == 0 {
+                return Err(syntax_error(0, "expected `[`"));
+            }
+            match self.byte(pos - 1)? {
+                // Continue with the byte before the opening quote.
+                b'"' => pos = self.string_start(pos - 1)? + 1,
+                b']' | b'}' => depth += 1,
+                b'[' if depth == 0 => break b'[',
+                b'{' if depth == 0 => return Err(syntax_error(pos - 1, "unmatched `{`")),
+                b'[' | b'{' => depth -= 1,
+                b',' if depth == 0 => break b',',
+                _ => {}
+            }
+            pos -= 1;
+        };
+        let start = self.skip_whitespace_forward(pos, end)?;
+        if start == end {
+            return Err(syntax_error(pos, "expected a value"));
+        }
+        if separator == b'[' {
+            self.array_start(pos - 1)?;
+        } else {
+            self.end = pos - 1;
+        }
+        self.object(start, end).map(Some)
+    }
+
+    /// Check that the array opened at `pos` is preceded only by whitespace.
+    fn array_start(&mut self, pos: u64) -> Result<()> {
+        self.end = pos;
+        self.state = ReverseState::Done;
+        let before = self.skip_whitespace(pos)?;
+        if before > 0 {
+            return Err(syntax_error(before, "leading characters before array"));
+        }
+        Ok(())
+    }
+
+    /// Return the offset of the opening quote of the string whose closing
+    /// quote is at `close`.
+    fn string_start(&mut self, close: u64) -> Result<u64> {
+        let mut pos = close;
+        while pos > 0 {
+            pos -= 1;
+            if self.byte(pos)? != b'"' {
+                continue;
+            }
+            let mut backslashes = 0;
+            while pos > backslashes && self.byte(pos - backslashes - 1)? == b'\\' {
+                backslashes += 1;
+            }
+            if backslashes % 2 == 0 {
+                return Ok(pos);
+            }
+        }
+        Err(syntax_error(close, "unterminated string"))
+    }
+
+    /// Move back over whitespace ending at `pos`.
+    fn skip_whitespace(&mut self, mut pos: u64) -> Result<u64> {
+        while pos > 0 && is_whitespace(self.byte(pos - 1)?) {
+            pos -= 1;
+        }
+        Ok(pos)
+    }
+
+    /// Move forward over whitespace starting at `pos`, but not past `end`.
+    fn skip_whitespace_forward(&mut self, mut pos: u64, end: u64) -> Result<u64> {
+        while pos < end && is_whitespace(self.byte(pos)?) {
+            pos += 1;
+        }
+        Ok(pos)
+    }
+
+    fn object(&mut self, start: u64, end: u64) -> Result<BytesObject> {
+        let bytes =
+            self.buf[(start - self.buf_start) as usize..(end - self.buf_start) as usize].to_vec();
+        Ok(BytesObject::from_parts(bytes, start))
+    }
+
+    /// The byte at `pos`, loading earlier blocks as needed.
+    fn byte(&mut self, pos: u64) -> Result<u8> {
+        while pos < self.buf_start {
+            let start = self.buf_start.saturating_sub(BLOCK_SIZE);
+            let mut block = vec![0; (self.buf_start - start) as usize];
+            self.rdr.seek(SeekFrom::Start(start))?;
+            self.rdr.read_exact(&mut block)?;
+            block.append(&mut self.buf);
+            self.buf = block;
+            self.buf_start = start;
+        }
+        Ok(self.buf[(pos - self.buf_start) as usize])
+    }
+}
+
+/// A borrowed iterator over the records of a [`ReverseReader`], from last
+/// to first.
+///
+/// The lifetime parameter `'r` refers to the lifetime of the underlying
+/// reader.
+#[derive(Debug)]
+pub struct DeserializeReverseIter<'r, R, D> {
+    rdr: &'r mut ReverseReader<R>,
+    done: bool,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<'r, R: io::Read + Seek, D: DeserializeOwned> Iterator for DeserializeReverseIter<'r, R, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        if self.done {
+            return None;
+        }
+        let result = match self.rdr.read_object() {
+            Ok(Some(obj)) => obj.deserialize(),
+            Ok(None) => return None,
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+fn syntax_error(position: u64, msg: &'static str) -> Error {
+    Error::SyntaxError { position, msg }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::reverse::ReverseReader;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn reads_json_lines_backwards() -> Result<(), Box<dyn Error>> {
+        let data = (0..50_000)
+            .map(|n| format!("{{\"n\":{}}}\n{}", n, if n % 9 == 0 { " \n" } else { "" }))
+            .collect::<String>();
+        let mut rdr = ReverseReader::json_lines(Cursor::new(data))?;
+        let values = rdr
+            .deserialize::<Value>()
+            .map(|value| value.map(|value| value["n"].as_u64().unwrap()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, (0..50_000).rev().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn walks_array_elements_backwards() -> Result<(), Box<dyn Error>> {
+        let records = vec![
+            json!({"s": "a, \"b\" ] [", "v": [1, {"x": "}"}]}),
+            json!("\\"),
+            json!("ends with \\\""),
+            json!(null),
+            json!(-1.5e3),
+            json!([[], {}]),
+        ];
+        let mut data = serde_json::to_string_pretty(&records)?;
+        data.insert_str(0, "  ");
+        data.push_str("\n\n");
+        let mut rdr = ReverseReader::array(Cursor::new(data))?;
+        let mut values = rdr.deserialize::<Value>().collect::<Result<Vec<_>, _>>()?;
+        values.reverse();
+        assert_eq!(values, records);
+
+        let mut rdr = ReverseReader::array(Cursor::new(serde_json::to_vec(&records)?))?;
+        let mut values = rdr.deserialize::<Value>().collect::<Result<Vec<_>, _>>()?;
+        values.reverse();
+        assert_eq!(values, records);
+
+        let mut rdr = ReverseReader::array(Cursor::new(" [ ] "))?;
+        assert_eq!(rdr.read_object()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_the_tail() -> Result<(), Box<dyn Error>> {
+        let data = serde_json::to_vec(&(0..100_000).collect::<Vec<u32>>())?;
+        let mut rdr = ReverseReader::array(Cursor::new(data))?;
+        assert_eq!(rdr.tail::<u32>(3)?, vec![99_997, 99_998, 99_999]);
+        assert_eq!(rdr.tail::<u32>(2)?, vec![99_995, 99_996]);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        for input in ["[1,2", "1,2]", "[1,,2]", "x[1]"] {
+            let mut rdr = ReverseReader::array(Cursor::new(input)).unwrap();
+            let results = rdr.deserialize::<u32>().collect::<Vec<_>>();
+            assert!(results.last().unwrap().is_err(), "{}", input);
+        }
+
+        let mut rdr = ReverseReader::array(Cursor::new("[1, {2]")).unwrap();
+        assert!(matches!(
+            rdr.read_object(),
+            Err(crate::error::Error::SyntaxError { position: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,352 @@
+//! Binary search over JSON Lines files sorted by a key.
+//!
+//! A [`SortedReader`] finds records in a JSON Lines file whose records are
+//! sorted by the value at a JSON Pointer, such as a timestamp, without any
+//! index: it bisects on byte offsets, resyncing to the next line start after
+//! each probe.
+use crate::bytes_object::BytesObject;
+use crate::decoder::is_whitespace;
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    marker::PhantomData,
+    ops::Range,
+    path::Path,
+};
+
+/// A reader that seeks by key in a sorted JSON Lines file.
+///
+/// Keys are the values at the reader's JSON Pointer, deserialized into `K`
+/// and compared with `Ord`. Every non-blank line must have a key, and the
+/// keys must be in non-decreasing order; otherwise seeking returns an
+/// unspecified position.
+#[derive(Debug)]
+pub struct SortedReader<R, K> {
+    rdr: BufReader<R>,
+    pointer: String,
+    len: u64,
+    pos: u64,
+    line: Vec<u8>,
+    _priv: PhantomData<fn() -> K>,
+}
+
+/// A non-blank line found while bisecting.
+struct Probe<K> {
+    start: u64,
+    end: u64,
+    key: K,
+}
+
+impl<K: DeserializeOwned + Ord> SortedReader<File, K> {
+    /// Open the sorted JSON Lines file at the given path, keyed by the value
+    /// at `pointer`.
+    pub fn from_path<P: AsRef<Path>>(path: P, pointer: &str) -> Result<SortedReader<File, K>> {
+        SortedReader::new(File::open(path)?, pointer)
+    }
+}
+
+impl<R: io::Read + Seek, K: DeserializeOwned + Ord> SortedReader<R, K> {
+    /// Create a reader for the sorted JSON Lines data in `rdr`, keyed by the
+    /// value at `pointer`, such as `/ts`.
+    ///
+    /// The reader starts at the beginning of the data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::sorted::SortedReader;
+    /// use serde_json::Value;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let data = (0..1000)
+    ///         .map(|ts| format!("{{\"ts\":{},\"msg\":\"event {}\"}}\n", ts * 10, ts))
+    ///         .collect::<String>();
+    ///     let mut rdr = SortedReader::<_, u64>::new(Cursor::new(data), "/ts")?;
+    ///     let events = rdr
+    ///         .range::<Value>(4995..5020)?
+    ///         .collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(events.len(), 2);
+    ///     assert_eq!(events[0]["msg"], "event 500");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new(rdr: R, pointer: &str) -> Result<SortedReader<R, K>> {
+        let mut rdr = BufReader::new(rdr);
+        let len = rdr.seek(SeekFrom::End(0))?;
+        rdr.seek(SeekFrom::Start(0))?;
+        Ok(SortedReader {
+            rdr,
+            pointer: pointer.to_string(),
+            len,
+            pos: 0,
+            line: Vec::new(),
+            _priv: PhantomData,
+        })
+    }
+
+    /// The byte offset of the next line to be read.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Move to the first record whose key is not less than `key`, and
+    /// return its byte offset.
+    ///
+    /// If all keys are less than `key`, this moves to the end of the data.
+    /// This takes a logarithmic number of seeks.
+    pub fn seek_to(&mut self, key: &K) -> Result<u64> {
+        // Every record starting before `lo` has a smaller key, and every
+        // record starting at or after `hi` does not.
+        let mut lo = 0;
+        let mut hi = self.len;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.probe(mid)? {
+                Some(probe) if probe.start < hi => {
+                    if probe.key < *key {
+                        lo = probe.end;
+                    } else {
+                        hi = probe.start;
+                    }
+                }
+                _ => hi = mid,
+            }
+        }
+        self.rdr.seek(SeekFrom::Start(lo))?;
+        self.pos = lo;
+        Ok(lo)
+    }
+
+    /// Read the raw bytes of the next record.
+    ///
+    /// Blank lines are skipped.
+    pub fn read_object(&mut self) -> Result<Option<BytesObject>> {
+        loop {
+            let line_start = self.pos;
+            self.line.clear();
+            let n = self.rdr.read_until(b'\n', &mut self.line)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.pos += n as u64;
+            if let Some((start, end)) = trim(&self.line) {
+                return Ok(Some(BytesObject::from_parts(
+                    self.line[start..end].to_vec(),
+                    line_start + start as u64,
+                )));
+            }
+        }
+    }
+
+    /// Returns a borrowed iterator over the deserialized records from the
+    /// current position to the end of the data.
+    pub fn deserialize<D: DeserializeOwned>(&mut self) -> DeserializeSortedIter<'_, R, K, D> {
+        DeserializeSortedIter {
+            rdr: self,
+            end: None,
+            done: false,
+            _priv: PhantomData,
+        }
+    }
+
+    /// Returns a borrowed iterator over the deserialized records whose keys
+    /// lie in `range`.
+    ///
+    /// This seeks to the start of the range, so the records are found
+    /// without scanning the data before them.
+    pub fn range<D: DeserializeOwned>(
+        &mut self,
+        range: Range<K>,
+    ) -> Result<DeserializeSortedIter<'_, R, K, D>> {
+        self.seek_to(&range.start)?;
+        Ok(DeserializeSortedIter {
+            rdr: self,
+            end: Some(range.end),
+            done: false,
+            _priv: PhantomData,
+        })
+    }
+
+    /// Find the first non-blank line starting at or after `pos`.
+    fn probe(&mut self, pos: u64) -> Result<Option<Probe<K>>> {
+        self.pos = if pos == 0 {
+            self.rdr.seek(SeekFrom::Start(0))?
+        } else {
+            // Skip the rest of the line containing `pos - 1`.
+            let pos = self.rdr.seek(SeekFrom::Start(pos - 1))?;
+            self.line.clear();
+            pos + self.rdr.read_until(b'\n', &mut self.line)? as u64
+        };
+        let obj = match self.read_object()? {
+            Some(obj) => obj,
+            None => return Ok(None),
+        };
+        let line_start = obj.position() - trim(&self.line).map_or(0, |(start, _)| start) as u64;
+        let key = self.key(&obj)?;
+        Ok(Some(Probe {
+            start: line_start,
+            end: self.pos,
+            key,
+        }))
+    }
+
+    fn key(&self, obj: &BytesObject) -> Result<K> {
+        let value: serde_json::Value = obj.deserialize()?;
+        key_of(&value, &self.pointer, obj.position())
+    }
+}
+
+/// A borrowed iterator over the deserialized records of a [`SortedReader`].
+///
+/// The lifetime parameter `'r` refers to the lifetime of the underlying
+/// reader.
+#[derive(Debug)]
+pub struct DeserializeSortedIter<'r, R, K, D> {
+    rdr: &'r mut SortedReader<R, K>,
+    end: Option<K>,
+    done: bool,
+    _priv: PhantomData<fn() -> D>,
+}
+
+impl<'r, R, K, D> DeserializeSortedIter<'r, R, K, D>
+where
+    R: io::Read + Seek,
+    K: DeserializeOwned + Ord,
+    D: DeserializeOwned,
+{
+    fn next_record(&mut self) -> Result<Option<D>> {
+        let obj = match self.rdr.read_object()? {
+            Some(obj) => obj,
+            None => return Ok(None),
+        };
+        let end = match &self.end {
+            Some(end) => end,
+            None => return obj.deserialize().map(Some),
+        };
+        let value: serde_json::Value = obj.deserialize()?;
+        let key: K = key_of(&value, &self.rdr.pointer, obj.position())?;
+        if key >= *end {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_value(value)?))
+    }
+}
+
+impl<'r, R, K, D> Iterator for DeserializeSortedIter<'r, R, K, D>
+where
+    R: io::Read + Seek,
+    K: DeserializeOwned + Ord,
+    D: DeserializeOwned,
+{
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        if self.done {
+            return None;
+        }
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn key_of<K: DeserializeOwned>(
+    value: &serde_json::Value,
+    pointer: &str,
+    position: u64,
+) -> Result<K> {
+    match value.pointer(pointer) {
+        Some(key) => Ok(K::deserialize(key)?),
+        None => Err(Error::SyntaxError {
+            position,
+            msg: "record has no value at the key pointer",
+        }),
+    }
+}
+
+/// The range of `line` without leading and trailing whitespace, or `None`
+/// if the line is blank.
+fn trim(line: &[u8]) -> Option<(usize, usize)> {
+    let start = line.iter().position(|&b| !is_whitespace(b))?;
+    let end = line.iter().rposition(|&b| !is_whitespace(b))? + 1;
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::sorted::SortedReader;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event {
+        ts: u64,
+        n: u32,
+    }
+
+    /// Events with timestamps `0, 0, 3, 3, 6, 6, ...` and some blank lines.
+    fn data() -> String {
+        let mut data = String::new();
+        for n in 0..300u32 {
+            data.push_str(&format!("{{\"ts\":{},\"n\":{}}}\n", n / 2 * 3, n));
+            if n % 7 == 0 {
+                data.push_str("  \n");
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn seeks_to_first_record_not_less_than_key() -> Result<(), Box<dyn Error>> {
+        let mut rdr = SortedReader::<_, u64>::new(Cursor::new(data()), "/ts")?;
+        for target in 0..460 {
+            rdr.seek_to(&target)?;
+            let next = rdr.deserialize::<Event>().next().transpose()?;
+            let expected = (0..300u32).find(|n| (n / 2 * 3) as u64 >= target);
+            assert_eq!(next.map(|event| event.n), expected, "target {}", target);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn scans_key_ranges() -> Result<(), Box<dyn Error>> {
+        let mut rdr = SortedReader::<_, u64>::new(Cursor::new(data()), "/ts")?;
+        let events = rdr.range::<Event>(3..9)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            events.iter().map(|event| event.n).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5]
+        );
+        assert_eq!(rdr.range::<Event>(1000..2000)?.count(), 0);
+        assert_eq!(rdr.range::<Event>(0..1000)?.count(), 300);
+        Ok(())
+    }
+
+    #[test]
+    fn compares_string_keys() -> Result<(), Box<dyn Error>> {
+        let data = "{\"t\":\"2024-01-01\"}\n{\"t\":\"2024-02-01\"}\n{\"t\":\"2024-03-01\"}\n";
+        let mut rdr = SortedReader::<_, String>::new(Cursor::new(data), "/t")?;
+        let offset = rdr.seek_to(&"2024-01-15".to_string())?;
+        assert_eq!(offset, 19);
+        let missing =
+            SortedReader::<_, String>::new(Cursor::new("{}\n"), "/t")?.seek_to(&"a".to_string());
+        assert!(missing.is_err());
+        Ok(())
+    }
+}
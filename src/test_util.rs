@@ -0,0 +1,60 @@
+//! Fixtures shared by the unit tests.
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A path in the temporary directory whose file is removed when dropped.
+#[derive(Debug)]
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    /// A fresh path ending in `name`, without creating the file.
+    pub(crate) fn new(name: &str) -> TempPath {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        TempPath(std::env::temp_dir().join(format!(
+            "json_arrays-{}-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+            name
+        )))
+    }
+
+    /// A fresh path ending in `name`, with a file holding `contents`.
+    pub(crate) fn with_contents<C: AsRef<[u8]>>(name: &str, contents: C) -> TempPath {
+        let path = TempPath::new(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Append `data` to the file, creating it if needed.
+    pub(crate) fn append(&self, data: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.0)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+}
+
+impl From<PathBuf> for TempPath {
+    /// Remove the file at `path` when dropped.
+    fn from(path: PathBuf) -> TempPath {
+        TempPath(path)
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
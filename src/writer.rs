@@ -1,15 +1,25 @@
 #[allow(unused_imports)]
-use crate::error::{Error, Result};
+use crate::error::{Error, Limit, Result};
 use serde::Serialize;
 use std::{
     fs::File,
-    io::{self, BufWriter},
+    io::{self, BufWriter, Seek, SeekFrom},
     path::Path,
+    sync::{Arc, Mutex},
 };
 
 #[derive(Debug)]
 pub struct WriterBuilder {
-    is_json_lines: bool,
+    pub(crate) is_json_lines: bool,
+    canonical: bool,
+    sort_keys: bool,
+    limits: WriteLimits,
+    always_valid: bool,
+    durability: Durability,
+    #[cfg(feature = "zstd")]
+    pub(crate) zstd_level: i32,
+    #[cfg(feature = "zstd")]
+    pub(crate) zstd_frame_records: usize,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -17,6 +27,15 @@ impl Default for WriterBuilder {
     fn default() -> Self {
         WriterBuilder {
             is_json_lines: false,
+            canonical: false,
+            sort_keys: false,
+            limits: WriteLimits::default(),
+            always_valid: false,
+            durability: Durability::None,
+            #[cfg(feature = "zstd")]
+            zstd_level: ::zstd_crate::DEFAULT_COMPRESSION_LEVEL,
+            #[cfg(feature = "zstd")]
+            zstd_frame_records: 1 << 10,
         }
     }
 }
@@ -55,7 +74,29 @@ impl WriterBuilder {
     /// }
     /// ```
     pub fn from_path<P: AsRef<Path>>(&self, path: P) -> Result<Writer<BufWriter<File>>> {
-        Ok(Writer::new(self, BufWriter::new(File::create(path)?)))
+        let path = path.as_ref();
+        let file = File::create(path)?;
+        if self.durability != Durability::None {
+            sync_parent_dir(path)?;
+        }
+        let mut wtr = self.from_seekable_writer(BufWriter::new(file));
+        wtr.state.sync = Some(|wtr: &mut BufWriter<File>| wtr.get_ref().sync_data());
+        Ok(wtr)
+    }
+
+    /// Build a JSON writer from this configuration that writes data to a
+    /// seekable `wtr`.
+    ///
+    /// This is like [`from_writer`](WriterBuilder::from_writer), but it
+    /// supports [`always_valid`](WriterBuilder::always_valid).
+    /// Like `from_writer`, it ignores
+    /// [`durability`](WriterBuilder::durability).
+    pub fn from_seekable_writer<W: io::Write + Seek>(&self, wtr: W) -> Writer<W> {
+        let mut wtr = Writer::new(self, wtr);
+        if self.always_valid && !self.is_json_lines {
+            wtr.state.seek_back = Some(|wtr: &mut W| wtr.seek(SeekFrom::Current(-1)).map(|_| ()));
+        }
+        wtr
     }
 
     /// Whether to write in json lines format.
@@ -108,27 +149,624 @@ impl WriterBuilder {
         self.is_json_lines = yes;
         self
     }
+
+    /// Whether to write each record as canonical JSON, as defined by
+    /// RFC 8785.
+    ///
+    /// Canonical records are byte-stable, so they can be hashed or signed:
+    /// object members are sorted, numbers are formatted as in ECMAScript,
+    /// and there is no insignificant whitespace. The array or JSON Lines
+    /// framing is unchanged. See the [`canonical`](crate::canonical) module
+    /// for details.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::WriterBuilder;
+    /// use serde_json::json;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///         .json_lines(true)
+    ///         .canonical(true)
+    ///         .from_writer(vec![]);
+    ///     wtr.serialize(json!({"b": 1.0, "a": 1e-7}))?;
+    ///     wtr.serialize(json!({"z": [], "\u{20ac}": "\u{1f}"}))?;
+    ///
+    ///     let data = String::from_utf8(wtr.into_inner()?)?;
+    ///     assert_eq!(data, "{\"a\":1e-7,\"b\":1}\n{\"z\":[],\"€\":\"\\u001f\"}");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn canonical(&mut self, yes: bool) -> &mut WriterBuilder {
+        self.canonical = yes;
+        self
+    }
+
+    /// Whether to sort the members of every object in a record by name.
+    ///
+    /// Sorting applies at every level of nesting, including to maps such
+    /// as `HashMap` whose iteration order changes between runs, so the same
+    /// records always produce the same output. Names are compared as Rust
+    /// strings, like the keys of a `BTreeMap`. Unlike
+    /// [`canonical`](WriterBuilder::canonical), numbers and strings are
+    /// written as usual. Records are serialized into memory before they are
+    /// written.
+    ///
+    /// This is disabled by default, and has no effect on canonical output,
+    /// which is always sorted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new().sort_keys(true).from_writer(vec![]);
+    ///     let scores = HashMap::from([("carol", 3), ("alice", 1), ("bob", 2)]);
+    ///     wtr.serialize(&scores)?;
+    ///
+    ///     let data = String::from_utf8(wtr.into_inner()?)?;
+    ///     assert_eq!(data, "[{\"alice\":1,\"bob\":2,\"carol\":3}]");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn sort_keys(&mut self, yes: bool) -> &mut WriterBuilder {
+        self.sort_keys = yes;
+        self
+    }
+
+    /// Whether to keep an array valid JSON after every flush.
+    ///
+    /// When enabled, [`Writer::flush`] ends the output with a closing `]`,
+    /// and the next record seeks back over it, so a reader that opens the
+    /// file after any flush sees a well-formed array. This needs a seekable
+    /// writer, so it only applies to writers built with
+    /// [`from_path`](WriterBuilder::from_path) or
+    /// [`from_seekable_writer`](WriterBuilder::from_seekable_writer). It has
+    /// no effect on JSON Lines.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///         .always_valid(true)
+    ///         .from_seekable_writer(Cursor::new(vec![]));
+    ///     wtr.serialize(1)?;
+    ///     wtr.flush()?;
+    ///     assert_eq!(wtr.get_ref().get_ref(), b"[1]");
+    ///     wtr.serialize(2)?;
+    ///     wtr.flush()?;
+    ///     assert_eq!(wtr.get_ref().get_ref(), b"[1,2]");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn always_valid(&mut self, yes: bool) -> &mut WriterBuilder {
+        self.always_valid = yes;
+        self
+    }
+
+    /// When to sync written data to disk.
+    ///
+    /// [`Writer::flush`] only hands data to the operating system, which may
+    /// lose it in a crash. With a policy other than [`Durability::None`],
+    /// the writer also calls `sync_data` on the file at the chosen points,
+    /// and syncs the parent directory when the file is created, so that the
+    /// file itself survives a crash.
+    ///
+    /// This only applies to writers built with
+    /// [`from_path`](WriterBuilder::from_path), which own the file. Writers
+    /// built with [`from_writer`](WriterBuilder::from_writer) or
+    /// [`from_seekable_writer`](WriterBuilder::from_seekable_writer) have no
+    /// file to sync, so they ignore this setting entirely: nothing is synced
+    /// and [`Durability::EveryNRecords`] does not flush either. To make such
+    /// output durable, sync the underlying file yourself after
+    /// [`Writer::close`].
+    ///
+    /// This defaults to [`Durability::None`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::writer::Durability;
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wal = WriterBuilder::new()
+    ///         .json_lines(true)
+    ///         .durability(Durability::EveryFlush)
+    ///         .from_path("wal.jsonl")?;
+    ///     wal.serialize(("set", "x", 1))?;
+    ///     // The entry is on disk once this returns.
+    ///     wal.flush()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn durability(&mut self, durability: Durability) -> &mut WriterBuilder {
+        self.durability = durability;
+        self
+    }
+
+    /// The maximum size of a single serialized record in bytes.
+    ///
+    /// Serializing a larger record fails with [`Limit::RecordBytes`] and
+    /// writes nothing, so the output stays valid. With any limit set,
+    /// records are serialized into memory before they are written.
+    ///
+    /// There is no limit by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use json_arrays::error::{Error, Limit};
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///         .max_record_bytes(8)
+    ///         .from_writer(vec![]);
+    ///     wtr.serialize("short")?;
+    ///     let result = wtr.serialize("far too long");
+    ///     assert!(matches!(
+    ///         result,
+    ///         Err(Error::LimitExceeded { limit: Limit::RecordBytes(8), .. })
+    ///     ));
+    ///     assert_eq!(wtr.into_inner()?, b"[\"short\"]");
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn max_record_bytes(&mut self, max: u64) -> &mut WriterBuilder {
+        self.limits.max_record_bytes = Some(max);
+        self
+    }
+
+    /// The maximum nesting depth of arrays and objects within a record.
+    ///
+    /// Serializing a record that nests deeper fails with [`Limit::Depth`].
+    ///
+    /// There is no limit by default.
+    pub fn max_depth(&mut self, max: usize) -> &mut WriterBuilder {
+        self.limits.max_depth = Some(max);
+        self
+    }
+
+    /// The maximum number of records.
+    ///
+    /// Serializing a further record fails with [`Limit::Records`].
+    ///
+    /// There is no limit by default.
+    pub fn max_records(&mut self, max: u64) -> &mut WriterBuilder {
+        self.limits.max_records = Some(max);
+        self
+    }
+
+    /// The maximum total size of the output in bytes, including the
+    /// closing `]` of an array.
+    ///
+    /// Serializing a record that would not fit fails with
+    /// [`Limit::TotalBytes`].
+    ///
+    /// There is no limit by default.
+    pub fn max_output_bytes(&mut self, max: u64) -> &mut WriterBuilder {
+        self.limits.max_output_bytes = Some(max);
+        self
+    }
+
+    /// Build a zstd writer from this configuration that writes compressed
+    /// data to `wtr`.
+    ///
+    /// The records are written with the framing of this configuration and
+    /// compressed as a single zstd stream, which any zstd tool can
+    /// decompress. The returned writer must be finished with
+    /// [`ZstdWriter::finish`](crate::zstd::ZstdWriter::finish). To read
+    /// record ranges without decompressing the whole stream, use
+    /// [`from_seekable_zstd_writer`](WriterBuilder::from_seekable_zstd_writer)
+    /// instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::{ReaderBuilder, WriterBuilder};
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///                    .json_lines(true)
+    ///                    .from_zstd_writer(vec![])?;
+    ///     for i in 0..5 {
+    ///         wtr.serialize(i)?;
+    ///     }
+    ///     let data = wtr.finish()?;
+    ///
+    ///     let mut rdr = ReaderBuilder::new()
+    ///         .json_lines(true)
+    ///         .from_zstd_reader(&data[..])?;
+    ///     let records = rdr.deserialize::<u32>().collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(records, vec![0, 1, 2, 3, 4]);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "zstd")]
+    pub fn from_zstd_writer<W: io::Write>(&self, wtr: W) -> Result<crate::zstd::ZstdWriter<W>> {
+        crate::zstd::ZstdWriter::new(self, wtr)
+    }
+
+    /// Build a zstd writer from this configuration that writes compressed
+    /// data to the given file path. The file is truncated if it already
+    /// exists.
+    ///
+    /// If there was a problem opening the file at the given path, then this
+    /// returns the corresponding error.
+    #[cfg(feature = "zstd")]
+    pub fn from_zstd_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<crate::zstd::ZstdWriter<BufWriter<File>>> {
+        self.from_zstd_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Build a seekable zstd writer from this configuration that writes
+    /// compressed data to `wtr`.
+    ///
+    /// The output is split into independent zstd frames every
+    /// [`zstd_frame_records`](WriterBuilder::zstd_frame_records) records and
+    /// ends with a seek table, so that a
+    /// [`SeekableReader`](crate::zstd::SeekableReader) can decompress only the
+    /// frames covering a given record range. The output is still a valid
+    /// zstd stream, so it can also be read with
+    /// [`ReaderBuilder::from_zstd_reader`](crate::ReaderBuilder::from_zstd_reader)
+    /// or decompressed with `zstd -d`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::WriterBuilder;
+    /// use json_arrays::zstd::SeekableReader;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///                    .zstd_frame_records(2)
+    ///                    .from_seekable_zstd_writer(vec![]);
+    ///     for i in 0..5 {
+    ///         wtr.serialize(i)?;
+    ///     }
+    ///     let data = wtr.finish()?;
+    ///
+    ///     let mut rdr = SeekableReader::new(Cursor::new(data))?;
+    ///     let records = rdr
+    ///         .deserialize_range::<u32>(3..5)
+    ///         .collect::<Result<Vec<_>, _>>()?;
+    ///     assert_eq!(records, vec![3, 4]);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "zstd")]
+    pub fn from_seekable_zstd_writer<W: io::Write>(
+        &self,
+        wtr: W,
+    ) -> crate::zstd::SeekableWriter<W> {
+        crate::zstd::SeekableWriter::new(self, wtr)
+    }
+
+    /// Build a seekable zstd writer from this configuration that writes
+    /// compressed data to the given file path. The file is truncated if it
+    /// already exists.
+    ///
+    /// If there was a problem opening the file at the given path, then this
+    /// returns the corresponding error.
+    #[cfg(feature = "zstd")]
+    pub fn from_seekable_zstd_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<crate::zstd::SeekableWriter<BufWriter<File>>> {
+        Ok(self.from_seekable_zstd_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    /// The zstd compression level used by zstd writers.
+    ///
+    /// This defaults to zstd's default level (currently `3`).
+    #[cfg(feature = "zstd")]
+    pub fn zstd_level(&mut self, level: i32) -> &mut WriterBuilder {
+        self.zstd_level = level;
+        self
+    }
+
+    /// The number of records written to each independent zstd frame by
+    /// seekable zstd writers.
+    ///
+    /// Smaller frames make partial reads cheaper at the cost of a worse
+    /// compression ratio. This defaults to `1024` and values below `1` are
+    /// treated as `1`.
+    #[cfg(feature = "zstd")]
+    pub fn zstd_frame_records(&mut self, records: usize) -> &mut WriterBuilder {
+        self.zstd_frame_records = records.max(1);
+        self
+    }
+
+    /// Build an asynchronous JSON writer from this configuration that writes
+    /// data to `wtr`.
+    ///
+    /// The returned writer must be closed with
+    /// [`AsyncWriter::close`](crate::async_writer::AsyncWriter::close).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// async fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///                    .json_lines(true)
+    ///                    .from_async_writer(vec![]);
+    ///     wtr.serialize("a").await?;
+    ///     wtr.serialize("x").await?;
+    ///     wtr.close().await?;
+    ///
+    ///     assert_eq!(wtr.get_ref(), b"\"a\"\n\"x\"");
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn from_async_writer<W: tokio::io::AsyncWrite + Unpin>(
+        &self,
+        wtr: W,
+    ) -> crate::async_writer::AsyncWriter<W> {
+        crate::async_writer::AsyncWriter::new(self, wtr)
+    }
+
+    /// Build a parallel JSON writer from this configuration that writes data
+    /// to `wtr`.
+    ///
+    /// Records are serialized on the rayon thread pool and written in the
+    /// order they were submitted, with the same framing as
+    /// [`from_writer`](WriterBuilder::from_writer).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    ///
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///                    .json_lines(true)
+    ///                    .from_parallel_writer(vec![]);
+    ///     for i in 0..3 {
+    ///         wtr.serialize(i)?;
+    ///     }
+    ///
+    ///     let data = String::from_utf8(wtr.into_inner()?)?;
+    ///     assert_eq!(data, "0\n1\n2");
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn from_parallel_writer<W: io::Write>(&self, wtr: W) -> crate::parallel::ParallelWriter<W> {
+        crate::parallel::ParallelWriter::new(self, wtr)
+    }
+
+    /// Build a JSON writer from this configuration that writes data to `wtr`
+    /// and records the byte offset of every record.
+    ///
+    /// The offsets are collected in a
+    /// [`RecordIndex`](crate::index::RecordIndex), which
+    /// [`IndexedWriter::finish`](crate::index::IndexedWriter::finish) returns
+    /// so it can be stored in a sidecar file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::error::Error;
+    /// use std::fs::File;
+    /// use std::io::BufWriter;
+    ///
+    /// use json_arrays::index::{sidecar_path, IndexedReader};
+    /// use json_arrays::WriterBuilder;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new()
+    ///                    .json_lines(true)
+    ///                    .from_indexed_writer(BufWriter::new(File::create("rows.json")?));
+    ///     for i in 0..1000 {
+    ///         wtr.serialize(i)?;
+    ///     }
+    ///     let (_, index) = wtr.finish()?;
+    ///     index.write_to_path(sidecar_path("rows.json"))?;
+    ///
+    ///     let mut rdr = IndexedReader::from_path("rows.json")?;
+    ///     assert_eq!(rdr.get::<u32>(500)?, Some(500));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn from_indexed_writer<W: io::Write>(&self, wtr: W) -> crate::index::IndexedWriter<W> {
+        crate::index::IndexedWriter::new(self, wtr)
+    }
+
+    /// Build a BGZF writer from this configuration that writes blocked gzip
+    /// data to `wtr`.
+    ///
+    /// The output is a valid multi-member gzip file that plain `gunzip` can
+    /// decompress. The writer records the virtual offset of every record in a
+    /// [`BgzfIndex`](crate::bgzf::BgzfIndex), which a
+    /// [`BgzfReader`](crate::bgzf::BgzfReader) uses to seek straight to a
+    /// record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::error::Error;
+    /// use std::io::Cursor;
+    ///
+    /// use json_arrays::WriterBuilder;
+    /// use json_arrays::bgzf::BgzfReader;
+    ///
+    /// # fn main() { example().unwrap(); }
+    /// fn example() -> Result<(), Box<dyn Error>> {
+    ///     let mut wtr = WriterBuilder::new().from_bgzf_writer(vec![]);
+    ///     wtr.serialize("a")?;
+    ///     wtr.serialize("x")?;
+    ///     let (data, index) = wtr.finish()?;
+    ///
+    ///     let mut rdr = BgzfReader::new(Cursor::new(data), index);
+    ///     assert_eq!(rdr.get::<String>(1)?, Some("x".to_string()));
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "bgzf")]
+    pub fn from_bgzf_writer<W: io::Write>(&self, wtr: W) -> crate::bgzf::BgzfWriter<W> {
+        crate::bgzf::BgzfWriter::new(self, wtr)
+    }
+
+    /// Build a BGZF writer from this configuration that writes blocked gzip
+    /// data to the given file path. The file is truncated if it already
+    /// exists.
+    ///
+    /// If there was a problem opening the file at the given path, then this
+    /// returns the corresponding error.
+    #[cfg(feature = "bgzf")]
+    pub fn from_bgzf_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<crate::bgzf::BgzfWriter<BufWriter<File>>> {
+        Ok(self.from_bgzf_writer(BufWriter::new(File::create(path)?)))
+    }
+}
+/// When a [`Writer`] syncs written data to disk.
+///
+/// Only writers built with [`WriterBuilder::from_path`] sync; see
+/// [`WriterBuilder::durability`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Never sync; leave it to the operating system.
+    None,
+    /// Sync when the writer is closed.
+    OnClose,
+    /// Flush and sync after every `n` records, and when the writer is
+    /// closed.
+    EveryNRecords(u64),
+    /// Sync on every flush, including the one when the writer is closed.
+    EveryFlush,
+}
+
+impl Default for Durability {
+    fn default() -> Durability {
+        Durability::None
+    }
 }
+
 #[derive(Debug)]
 pub struct Writer<W: io::Write> {
     wtr: Option<W>,
-    state: WriterState,
+    state: WriterState<W>,
 }
 
 #[derive(Debug)]
-struct WriterState {
+struct WriterState<W> {
     array_start: ArrayState,
     array_end: ArrayState,
     delimiter: DelimiterState,
     delimiter_token: [u8; 1],
-    panicked: bool,
+    /// Set while a record or its framing is being written, like `panicked`
+    /// in `std::io::BufWriter`. It stays set for good if the write panics
+    /// or fails halfway, which leaves the output invalid, so that nothing
+    /// more is written and dropping the writer does not close it.
+    poisoned: bool,
+    drop_error: Option<DropErrorHandle>,
+    format: RecordFormat,
+    limits: WriteLimits,
+    records: u64,
+    /// The number of bytes written so far. This is only tracked while
+    /// limits are set.
+    written: u64,
+    /// Moves back by one byte, for writers that keep arrays valid after
+    /// every flush.
+    seek_back: Option<fn(&mut W) -> io::Result<()>>,
+    /// Whether a closing `]` was written by a flush, to be overwritten by
+    /// the next record.
+    provisional_end: bool,
+    durability: Durability,
+    /// Syncs the written data to disk, for file-backed writers.
+    sync: Option<fn(&mut W) -> io::Result<()>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct WriteLimits {
+    max_record_bytes: Option<u64>,
+    max_depth: Option<usize>,
+    max_records: Option<u64>,
+    max_output_bytes: Option<u64>,
+}
+
+impl WriteLimits {
+    fn is_set(&self) -> bool {
+        self.max_record_bytes.is_some()
+            || self.max_depth.is_some()
+            || self.max_records.is_some()
+            || self.max_output_bytes.is_some()
+    }
+}
+
+/// How a record is serialized.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RecordFormat {
+    Plain,
+    SortedKeys,
+    Canonical,
+}
+
+impl RecordFormat {
+    pub(crate) fn to_writer<W: io::Write, S: Serialize + ?Sized>(
+        self,
+        wtr: W,
+        record: &S,
+    ) -> serde_json::Result<()> {
+        match self {
+            RecordFormat::Plain => serde_json::to_writer(wtr, record),
+            RecordFormat::SortedKeys => crate::canonical::to_writer_sorted(wtr, record),
+            RecordFormat::Canonical => crate::canonical::to_writer(wtr, record),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 enum ArrayState {
     Write,
     DidWrite,
-    DidNotWrite,
     None,
 }
 #[derive(Debug, Clone, Copy)]
@@ -137,10 +775,30 @@ enum DelimiterState {
     WriteNext,
 }
 
+/// Receives the error, if any, from closing a [`Writer`] when it is
+/// dropped.
+///
+/// See [`Writer::drop_error_handle`].
+#[derive(Debug, Clone, Default)]
+pub struct DropErrorHandle(Arc<Mutex<Option<Error>>>);
+
+impl DropErrorHandle {
+    /// Take the error from closing the writer when it was dropped.
+    ///
+    /// Returns `None` if the writer has not been dropped yet, or if it was
+    /// closed successfully.
+    pub fn take(&self) -> Option<Error> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+}
+
 impl<W: io::Write> Drop for Writer<W> {
     fn drop(&mut self) {
-        if self.wtr.is_some() && !self.state.panicked {
-            let _ = self.close();
+        if self.wtr.is_none() {
+            return;
+        }
+        if let (Err(err), Some(handle)) = (self.close(), &self.state.drop_error) {
+            *handle.0.lock().unwrap_or_else(|err| err.into_inner()) = Some(err);
         }
     }
 }
@@ -192,7 +850,22 @@ impl<W: io::Write> Writer<W> {
                 array_end: array_state,
                 delimiter: DelimiterState::WriteNext,
                 delimiter_token,
-                panicked: false,
+                poisoned: false,
+                drop_error: None,
+                format: if builder.canonical {
+                    RecordFormat::Canonical
+                } else if builder.sort_keys {
+                    RecordFormat::SortedKeys
+                } else {
+                    RecordFormat::Plain
+                },
+                limits: builder.limits,
+                records: 0,
+                written: 0,
+                seek_back: None,
+                provisional_end: false,
+                durability: builder.durability,
+                sync: None,
             },
         }
     }
@@ -248,20 +921,7 @@ impl<W: io::Write> Writer<W> {
     /// }
     /// ```
     pub fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
-        if let ArrayState::Write = self.state.array_start {
-            let wrote_array_start = self.write_array_start();
-            if wrote_array_start {
-                self.state.array_start = ArrayState::DidWrite;
-            } else {
-                self.state.array_start = ArrayState::DidNotWrite;
-            }
-        }
-        match self.state.delimiter {
-            DelimiterState::Write => self.write_delimiter()?,
-            DelimiterState::WriteNext => self.state.delimiter = DelimiterState::Write,
-        };
-        // self.write_terminator()?;
-        serde_json::to_writer(self.wtr.as_mut().unwrap(), &record).unwrap();
+        self.write_record(record, |_| {})?;
         // if let HeaderState::Write = self.state.header {
         //     let wrote_header = serialize_header(self, &record)?;
         //     if wrote_header {
@@ -276,45 +936,318 @@ impl<W: io::Write> Writer<W> {
         Ok(())
     }
 
-    fn write_array_start(&mut self) -> bool {
-        self.wtr.as_mut().unwrap().write(b"[").is_ok()
+    /// Serialize a record with the framing before it, calling `at_start`
+    /// once the framing is written.
+    ///
+    /// Nothing is written if the record exceeds a limit.
+    pub(crate) fn write_record<S: Serialize>(
+        &mut self,
+        record: S,
+        at_start: impl FnOnce(&W),
+    ) -> Result<()> {
+        self.check_poisoned()?;
+        if let (RecordFormat::Plain, false) = (self.state.format, self.state.limits.is_set()) {
+            self.write_record_start()?;
+            at_start(self.get_ref());
+            self.write_value(record)?;
+        } else {
+            let mut bytes = Vec::with_capacity(128);
+            self.state.format.to_writer(&mut bytes, &record)?;
+            self.check_limits(&bytes)?;
+            self.write_record_start()?;
+            at_start(self.get_ref());
+            self.write_bytes(&bytes)?;
+        }
+        self.sync_every_n_records()
+    }
+
+    /// Write a record that has already been serialized to JSON.
+    ///
+    /// `record` must hold exactly one JSON value.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn write_raw(&mut self, record: &[u8]) -> Result<()> {
+        self.check_poisoned()?;
+        self.check_limits(record)?;
+        self.write_record_start()?;
+        self.write_bytes(record)?;
+        self.sync_every_n_records()
+    }
+
+    /// Whether the writer writes JSON Lines.
+    pub fn is_json_lines(&self) -> bool {
+        self.state.delimiter_token == [b'\n']
+    }
+
+    /// How records are serialized.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn format(&self) -> RecordFormat {
+        self.state.format
+    }
+
+    fn sync_every_n_records(&mut self) -> Result<()> {
+        if let Durability::EveryNRecords(n) = self.state.durability {
+            if self.state.sync.is_some() && self.state.records % n.max(1) == 0 {
+                self.flush()?;
+                self.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        match self.state.sync {
+            Some(sync) => sync(self.wtr.as_mut().unwrap()),
+            None => Ok(()),
+        }
+    }
+
+    /// Return an error if an earlier write failed or panicked in the middle
+    /// of a record or its framing.
+    ///
+    /// The output is then invalid for good, so the writer refuses to write
+    /// anything more, even if the underlying writer works again.
+    fn check_poisoned(&self) -> io::Result<()> {
+        if self.state.poisoned {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "an earlier write failed in the middle of a record, so the output is invalid",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write `bytes`, marking the writer poisoned unless all of them are
+    /// written.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.check_poisoned()?;
+        self.state.poisoned = true;
+        self.wtr.as_mut().unwrap().write_all(bytes)?;
+        self.state.poisoned = false;
+        self.state.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Check that writing the serialized `record` next stays within the
+    /// limits.
+    fn check_limits(&self, record: &[u8]) -> Result<()> {
+        let limits = &self.state.limits;
+        let framing = matches!(self.state.array_start, ArrayState::Write) as u64
+            + matches!(self.state.delimiter, DelimiterState::Write) as u64;
+        let closing = matches!(self.state.array_end, ArrayState::Write) as u64;
+        let len = record.len() as u64;
+        let limit = match *limits {
+            WriteLimits {
+                max_records: Some(max),
+                ..
+            } if self.state.records >= max => Limit::Records(max),
+            WriteLimits {
+                max_record_bytes: Some(max),
+                ..
+            } if len > max => Limit::RecordBytes(max),
+            WriteLimits {
+                max_output_bytes: Some(max),
+                ..
+            } if self.state.written + framing + len + closing > max => Limit::TotalBytes(max),
+            WriteLimits {
+                max_depth: Some(max),
+                ..
+            } if nesting_depth(record) > max => Limit::Depth(max),
+            _ => return Ok(()),
+        };
+        Err(Error::LimitExceeded {
+            limit,
+            position: self.state.written + framing,
+        })
+    }
+
+    /// Write whatever precedes the next record: the opening `[` or a
+    /// delimiter.
+    pub(crate) fn write_record_start(&mut self) -> Result<()> {
+        if self.state.provisional_end {
+            (self.state.seek_back.unwrap())(self.wtr.as_mut().unwrap())?;
+            self.state.provisional_end = false;
+        }
+        self.write_array_start()?;
+        match self.state.delimiter {
+            DelimiterState::Write => self.write_delimiter()?,
+            DelimiterState::WriteNext => self.state.delimiter = DelimiterState::Write,
+        };
+        self.state.records += 1;
+        Ok(())
+    }
+
+    /// Serialize a record without any framing around it.
+    pub(crate) fn write_value<S: Serialize>(&mut self, record: S) -> Result<()> {
+        // self.write_terminator()?;
+        self.check_poisoned()?;
+        self.state.poisoned = true;
+        serde_json::to_writer(self.wtr.as_mut().unwrap(), &record)?;
+        self.state.poisoned = false;
+        Ok(())
     }
 
-    fn write_array_end(&mut self) -> bool {
-        self.wtr.as_mut().unwrap().write(b"]").is_ok()
+    fn write_array_start(&mut self) -> io::Result<()> {
+        if let ArrayState::Write = self.state.array_start {
+            self.write_bytes(b"[")?;
+            self.state.array_start = ArrayState::DidWrite;
+        }
+        Ok(())
     }
 
-    fn write_delimiter(&mut self) -> Result<()> {
-        self.wtr
-            .as_mut()
-            .unwrap()
-            .write_all(&self.state.delimiter_token)
-            .unwrap();
+    fn write_array_end(&mut self) -> io::Result<()> {
+        if let ArrayState::Write = self.state.array_end {
+            // An array without records still needs its opening `[`.
+            self.write_array_start()?;
+            self.write_bytes(b"]")?;
+            self.state.array_end = ArrayState::DidWrite;
+        }
         Ok(())
     }
+
+    fn write_delimiter(&mut self) -> io::Result<()> {
+        let delimiter_token = self.state.delimiter_token;
+        self.write_bytes(&delimiter_token)
+    }
+    /// Return a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.wtr.as_ref().unwrap()
+    }
+
+    /// Return a mutable reference to the underlying writer.
+    ///
+    /// Writing to the underlying writer directly may corrupt the JSON
+    /// output.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.wtr.as_mut().unwrap()
+    }
+
     pub fn flush(&mut self) -> io::Result<()> {
         // self.flush_buf()?;
+        self.check_poisoned()?;
+        let is_open = matches!(self.state.array_end, ArrayState::Write);
+        if self.state.seek_back.is_some() && is_open && !self.state.provisional_end {
+            self.write_array_start()?;
+            self.wtr.as_mut().unwrap().write_all(b"]")?;
+            self.state.provisional_end = true;
+        }
         self.wtr.as_mut().unwrap().flush()?;
+        if self.state.durability == Durability::EveryFlush {
+            self.sync()?;
+        }
         Ok(())
     }
+
+    /// Finish the output and flush it. For arrays this writes the closing
+    /// `]`.
+    ///
+    /// If an earlier write failed or panicked in the middle of a record or
+    /// its framing, this returns an error and writes nothing, since closing
+    /// would not make the output valid.
     pub fn close(&mut self) -> Result<()> {
-        if let ArrayState::Write = self.state.array_end {
-            let wrote_array_end = self.write_array_end();
-            if wrote_array_end {
-                self.state.array_end = ArrayState::DidWrite;
-            } else {
-                self.state.array_end = ArrayState::DidNotWrite;
-            }
+        self.check_poisoned()?;
+        if self.state.provisional_end {
+            // The last flush already closed the array.
+            self.state.array_end = ArrayState::DidWrite;
         }
+        self.write_array_end()?;
         self.flush()?;
+        if let Durability::OnClose | Durability::EveryNRecords(_) = self.state.durability {
+            self.sync()?;
+        }
         Ok(())
     }
     pub fn into_inner(mut self) -> Result<W> {
-        match self.close() {
-            Ok(()) => Ok(self.wtr.take().unwrap()),
-            Err(err) => todo!("handle err {:?}", err),
+        self.close()?;
+        Ok(self.wtr.take().unwrap())
+    }
+
+    /// Returns a handle that receives the error from closing the writer
+    /// when it is dropped.
+    ///
+    /// Dropping a writer closes it, but any error is otherwise discarded.
+    /// If the writer is dropped in the middle of a record, for example
+    /// while unwinding from a panic in a `Serialize` implementation or
+    /// after a failed write, the output is left as it is, without closing
+    /// bytes, and the handle receives an error saying so.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io;
+    ///
+    /// use json_arrays::Writer;
+    ///
+    /// struct Full;
+    ///
+    /// impl io::Write for Full {
+    ///     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+    ///         Ok(0)
+    ///     }
+    ///
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut wtr = Writer::from_writer(io::BufWriter::new(Full));
+    /// let handle = wtr.drop_error_handle();
+    /// wtr.serialize(1).unwrap();
+    /// drop(wtr);
+    /// assert!(handle.take().is_some());
+    /// ```
+    pub fn drop_error_handle(&mut self) -> DropErrorHandle {
+        self.state
+            .drop_error
+            .get_or_insert_with(DropErrorHandle::default)
+            .clone()
+    }
+}
+
+/// Sync the directory containing `path`, so that the creation of the file
+/// survives a crash.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+/// Directories cannot be opened for syncing on this platform.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The deepest nesting of arrays and objects in the serialized `json`.
+fn nesting_depth(json: &[u8]) -> usize {
+    let mut depth = 0;
+    let mut max = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &b in json {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            b'}' | b']' => depth -= 1,
+            _ => {}
         }
     }
+    max
 }
 
 #[cfg(test)]
@@ -322,6 +1255,7 @@ mod tests {
 
     use std::error::Error;
 
+    use crate::test_util::TempPath;
     use crate::writer::{Writer, WriterBuilder};
     use serde::Serialize;
 
@@ -378,4 +1312,340 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn enforces_limits() -> Result<(), Box<dyn Error>> {
+        use crate::error::{Error as JsonError, Limit};
+        use serde_json::json;
+
+        fn limit_of(result: crate::error::Result<()>) -> Option<Limit> {
+            match result {
+                Err(JsonError::LimitExceeded { limit, .. }) => Some(limit),
+                _ => None,
+            }
+        }
+
+        let mut wtr = WriterBuilder::new()
+            .max_depth(2)
+            .max_records(3)
+            .from_writer(vec![]);
+        wtr.serialize(json!({"a": [1, "]]]"]}))?;
+        assert_eq!(
+            limit_of(wtr.serialize(json!([[[1]]]))),
+            Some(Limit::Depth(2))
+        );
+        wtr.serialize(2)?;
+        wtr.serialize(3)?;
+        assert_eq!(limit_of(wtr.serialize(4)), Some(Limit::Records(3)));
+        assert_eq!(
+            String::from_utf8(wtr.into_inner()?)?,
+            r#"[{"a":[1,"]]]"]},2,3]"#
+        );
+
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .max_output_bytes(8)
+            .from_writer(vec![]);
+        wtr.serialize("abc")?;
+        wtr.serialize(12)?;
+        assert_eq!(limit_of(wtr.serialize(1)), Some(Limit::TotalBytes(8)));
+        assert_eq!(wtr.into_inner()?, b"\"abc\"\n12");
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_arrays_valid_after_every_flush() -> Result<(), Box<dyn Error>> {
+        use std::io::Cursor;
+
+        let mut wtr = WriterBuilder::new()
+            .always_valid(true)
+            .from_seekable_writer(Cursor::new(vec![]));
+        wtr.flush()?;
+        assert_eq!(wtr.get_ref().get_ref(), b"[]");
+        wtr.serialize("a")?;
+        wtr.flush()?;
+        wtr.flush()?;
+        assert_eq!(wtr.get_ref().get_ref(), b"[\"a\"]");
+        wtr.serialize("b")?;
+        wtr.serialize("c")?;
+        wtr.flush()?;
+        assert_eq!(wtr.get_ref().get_ref(), b"[\"a\",\"b\",\"c\"]");
+        assert_eq!(wtr.into_inner()?.into_inner(), b"[\"a\",\"b\",\"c\"]");
+
+        let mut wtr = WriterBuilder::new()
+            .always_valid(true)
+            .from_seekable_writer(Cursor::new(vec![]));
+        wtr.serialize(1)?;
+        wtr.serialize(2)?;
+        assert_eq!(wtr.into_inner()?.into_inner(), b"[1,2]");
+
+        let path = TempPath::new("valid.json");
+        let mut wtr = WriterBuilder::new().always_valid(true).from_path(&path)?;
+        for n in 0..100u32 {
+            wtr.serialize(n)?;
+            if n % 10 == 0 {
+                wtr.flush()?;
+                let values: Vec<u32> = serde_json::from_slice(&std::fs::read(&path)?)?;
+                assert_eq!(values, (0..=n).collect::<Vec<_>>());
+            }
+        }
+        wtr.close()?;
+        let values: Vec<u32> = serde_json::from_slice(&std::fs::read(&path)?)?;
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn syncs_every_n_records() -> Result<(), Box<dyn Error>> {
+        use crate::writer::Durability;
+
+        let path = TempPath::new("durable.jsonl");
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .durability(Durability::EveryNRecords(3))
+            .from_path(&path)?;
+        for n in 0..7u32 {
+            wtr.serialize(n)?;
+        }
+        // The first six records were flushed to the file with their syncs.
+        assert_eq!(std::fs::read(&path)?, b"0\n1\n2\n3\n4\n5");
+        wtr.close()?;
+        assert_eq!(std::fs::read(&path)?, b"0\n1\n2\n3\n4\n5\n6");
+
+        for durability in [Durability::OnClose, Durability::EveryFlush] {
+            let mut wtr = WriterBuilder::new()
+                .durability(durability)
+                .from_path(&path)?;
+            wtr.serialize(1)?;
+            wtr.flush()?;
+            wtr.serialize(2)?;
+            wtr.close()?;
+            assert_eq!(std::fs::read(&path)?, b"[1,2]");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writes_canonical_records() -> Result<(), Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new()
+            .canonical(true)
+            .max_record_bytes(64)
+            .from_writer(vec![]);
+        wtr.serialize(Row {
+            city: "Boston",
+            country: "United States",
+            population: 4628910,
+        })?;
+        wtr.serialize(serde_json::json!({"b": [1e21, 0.1, -0.0], "a": {"d": 1, "c": null}}))?;
+        assert!(wtr.serialize("x".repeat(100)).is_err());
+        let data = String::from_utf8(wtr.into_inner()?)?;
+        assert_eq!(
+            data,
+            "[{\"city\":\"Boston\",\"country\":\"United States\",\"popcount\":4628910},\
+             {\"a\":{\"c\":null,\"d\":1},\"b\":[1e+21,0.1,0]}]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_keys_of_nested_maps() -> Result<(), Box<dyn Error>> {
+        use std::collections::HashMap;
+
+        #[derive(Serialize)]
+        struct Record {
+            z: u64,
+            tags: HashMap<String, Vec<HashMap<String, f64>>>,
+        }
+
+        let tags = (0..20)
+            .map(|n| {
+                let inner = HashMap::from([(format!("k{}", n), 1e21), ("a".to_string(), 0.5)]);
+                (format!("t{:02}", n), vec![inner])
+            })
+            .collect::<HashMap<_, _>>();
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .sort_keys(true)
+            .from_writer(vec![]);
+        wtr.serialize(Record { z: u64::MAX, tags })?;
+        let data = String::from_utf8(wtr.into_inner()?)?;
+
+        let tags = (0..20)
+            .map(|n| format!("\"t{:02}\":[{{\"a\":0.5,\"k{}\":1e+21}}]", n, n))
+            .collect::<Vec<_>>()
+            .join(",");
+        // Struct fields are sorted too, and numbers are written as usual.
+        assert_eq!(
+            data,
+            format!("{{\"tags\":{{{}}},\"z\":{}}}", tags, u64::MAX)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_keys_without_converting_numbers() -> Result<(), Box<dyn Error>> {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        struct Record {
+            single: f32,
+            wide: u128,
+            negative: i128,
+            names: BTreeMap<&'static str, Vec<f32>>,
+        }
+
+        let names = BTreeMap::from([(" ", vec![]), ("a\"b", vec![0.1]), ("\n", vec![1.1, 2.5])]);
+        let mut wtr = WriterBuilder::new()
+            .json_lines(true)
+            .sort_keys(true)
+            .from_writer(vec![]);
+        wtr.serialize(Record {
+            single: 1.1,
+            wide: u128::MAX,
+            negative: i128::MIN,
+            names,
+        })?;
+        assert_eq!(
+            String::from_utf8(wtr.into_inner()?)?,
+            format!(
+                "{{\"names\":{{\"\\n\":[1.1,2.5],\" \":[],\"a\\\"b\":[0.1]}},\
+                 \"negative\":{},\"single\":1.1,\"wide\":{}}}",
+                i128::MIN,
+                u128::MAX
+            )
+        );
+        Ok(())
+    }
+
+    /// Panics when serialized.
+    struct Bomb;
+
+    impl Serialize for Bomb {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            panic!("boom")
+        }
+    }
+
+    #[test]
+    fn does_not_close_after_a_panic_mid_record() {
+        let mut out = vec![];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut wtr = Writer::from_writer(&mut out);
+            wtr.serialize(1).unwrap();
+            wtr.serialize((2, Bomb)).unwrap();
+        }));
+        assert!(result.is_err());
+        assert_eq!(out, b"[1,[2,");
+    }
+
+    #[test]
+    fn reports_drop_errors_to_the_handle() -> Result<(), Box<dyn Error>> {
+        let mut out = vec![];
+        let mut wtr = Writer::from_writer(&mut out);
+        let handle = wtr.drop_error_handle();
+        wtr.serialize(1)?;
+        drop(wtr);
+        assert!(handle.take().is_none());
+        assert_eq!(out, b"[1]");
+
+        // Map keys must be strings, so this fails after writing `{`.
+        let mut out = vec![];
+        let mut wtr = Writer::from_writer(&mut out);
+        let handle = wtr.drop_error_handle();
+        wtr.serialize(1)?;
+        let record = std::collections::BTreeMap::from([((1, 2), 3)]);
+        assert!(wtr.serialize(record).is_err());
+        drop(wtr);
+        assert!(handle.take().is_some());
+        assert_eq!(out, b"[1,{");
+        Ok(())
+    }
+
+    /// Fails the write with the given index and accepts all others.
+    struct FailOnce {
+        fail_at: usize,
+        writes: usize,
+        out: Vec<u8>,
+    }
+
+    impl FailOnce {
+        fn new(fail_at: usize) -> FailOnce {
+            FailOnce {
+                fail_at,
+                writes: 0,
+                out: vec![],
+            }
+        }
+    }
+
+    impl std::io::Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            if self.writes - 1 == self.fail_at {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "full"));
+            }
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_empty_arrays() -> Result<(), Box<dyn Error>> {
+        assert_eq!(Writer::from_writer(vec![]).into_inner()?, b"[]");
+        let wtr = WriterBuilder::new().json_lines(true).from_writer(vec![]);
+        assert_eq!(wtr.into_inner()?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn does_not_close_after_failed_framing() -> Result<(), Box<dyn Error>> {
+        // `[` and `1` are written, then the delimiter fails.
+        let mut wtr = Writer::from_writer(FailOnce::new(2));
+        wtr.serialize(1)?;
+        assert!(wtr.serialize(2).is_err());
+        assert!(wtr.close().is_err());
+        assert_eq!(wtr.get_ref().out, b"[1");
+
+        // The opening `[` fails.
+        let mut out = FailOnce::new(0);
+        let mut wtr = Writer::from_writer(&mut out);
+        let handle = wtr.drop_error_handle();
+        assert!(wtr.serialize(1).is_err());
+        drop(wtr);
+        assert!(handle.take().is_some());
+        assert_eq!(out.out, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn stays_poisoned_after_a_failed_record() -> Result<(), Box<dyn Error>> {
+        struct FailsMidway;
+
+        impl Serialize for FailsMidway {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::{Error as _, SerializeMap};
+
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("a", &1)?;
+                Err(S::Error::custom("failed midway"))
+            }
+        }
+
+        for always_valid in [false, true] {
+            let mut wtr = WriterBuilder::new()
+                .always_valid(always_valid)
+                .from_seekable_writer(std::io::Cursor::new(Vec::new()));
+            wtr.serialize(1)?;
+            assert!(wtr.serialize(FailsMidway).is_err());
+            assert!(wtr.serialize(2).is_err());
+            assert!(wtr.flush().is_err());
+            assert!(wtr.close().is_err());
+            assert_eq!(wtr.get_ref().get_ref(), b"[1,{\"a\":1");
+        }
+        Ok(())
+    }
 }
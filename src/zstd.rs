@@ -0,0 +1,685 @@
+//! Zstd compression for JSON arrays and JSON Lines.
+//!
+//! A [`ZstdWriter`] compresses records as a single zstd stream, and
+//! [`ReaderBuilder::from_zstd_reader`](crate::ReaderBuilder::from_zstd_reader)
+//! reads any zstd stream of records with a [`Reader`], decompressing it as
+//! it goes.
+//!
+//! For cheap partial reads, a [`SeekableWriter`] splits its output into independent zstd frames every
+//! N records and finishes the stream with a seek table in the
+//! [zstd seekable format]. Because every frame holds a known number of
+//! records, a [`SeekableReader`] can jump straight to the frames covering a
+//! record range and decompress only those.
+//!
+//! The first frame is a skippable frame describing the framing (array or
+//! JSON Lines, records per frame). Regular zstd tools ignore it, so
+//! `zstd -d` still yields the plain JSON text.
+//!
+//! [zstd seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+use crate::decoder::is_whitespace;
+use crate::error::{Error, Result};
+use crate::writer::{Writer, WriterBuilder};
+#[cfg(doc)]
+use crate::Reader;
+use serde::{de::DeserializeOwned, de::IgnoredAny, Serialize};
+use std::{
+    fmt,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    ops::Range,
+};
+
+const SKIPPABLE_HEADER_SIZE: u64 = 8;
+const METADATA_FRAME_MAGIC: u32 = 0x184D_2A50;
+const METADATA_MAGIC: &[u8; 4] = b"JARR";
+const METADATA_VERSION: u8 = 1;
+const METADATA_SIZE: u32 = 10;
+const SEEK_TABLE_FRAME_MAGIC: u32 = 0x184D_2A5E;
+const SEEK_TABLE_FOOTER_SIZE: u64 = 9;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// A writer that compresses records into a zstd stream.
+///
+/// Create one with
+/// [`WriterBuilder::from_zstd_writer`](crate::WriterBuilder::from_zstd_writer)
+/// and call [`finish`](ZstdWriter::finish) once all records are written.
+/// If the writer is dropped without being finished, the stream is finished
+/// on a best-effort basis and any errors are ignored.
+pub struct ZstdWriter<W: io::Write> {
+    wtr: Option<Writer<::zstd_crate::Encoder<'static, W>>>,
+}
+
+impl<W: io::Write + fmt::Debug> fmt::Debug for ZstdWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZstdWriter")
+            .field(
+                "inner",
+                &self.wtr.as_ref().map(|wtr| wtr.get_ref().get_ref()),
+            )
+            .finish()
+    }
+}
+
+impl<W: io::Write> Drop for ZstdWriter<W> {
+    fn drop(&mut self) {
+        if let Some(wtr) = self.wtr.take() {
+            let _ = wtr.into_inner().map(::zstd_crate::Encoder::finish);
+        }
+    }
+}
+
+impl<W: io::Write> ZstdWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> Result<ZstdWriter<W>> {
+        let encoder = ::zstd_crate::Encoder::new(wtr, builder.zstd_level)?;
+        Ok(ZstdWriter {
+            wtr: Some(builder.from_writer(encoder)),
+        })
+    }
+
+    /// Serialize a single record using Serde.
+    pub fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
+        self.wtr.as_mut().unwrap().serialize(record)
+    }
+
+    /// Compress and write out all records written so far.
+    ///
+    /// This ends the current zstd block, so flushing often hurts the
+    /// compression ratio.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wtr.as_mut().unwrap().flush()
+    }
+
+    /// Finish the output, end the zstd stream and return the underlying
+    /// writer.
+    ///
+    /// For arrays this writes the closing `]`.
+    pub fn finish(mut self) -> Result<W> {
+        let encoder = self.wtr.take().unwrap().into_inner()?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// A decompressing reader over a zstd stream, as read by a [`Reader`] built
+/// with [`ReaderBuilder::from_zstd_reader`](crate::ReaderBuilder::from_zstd_reader).
+///
+/// Streams of several frames, such as those written by a
+/// [`SeekableWriter`], are decompressed frame after frame, and skippable
+/// frames are skipped.
+pub struct ZstdReader<R: Read> {
+    rdr: ::zstd_crate::Decoder<'static, BufReader<R>>,
+}
+
+impl<R: Read + fmt::Debug> fmt::Debug for ZstdReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZstdReader")
+            .field("inner", self.get_ref())
+            .finish()
+    }
+}
+
+impl<R: Read> ZstdReader<R> {
+    pub(crate) fn new(rdr: R) -> Result<ZstdReader<R>> {
+        Ok(ZstdReader {
+            rdr: ::zstd_crate::Decoder::new(rdr)?,
+        })
+    }
+
+    /// Return a reference to the underlying compressed reader.
+    pub fn get_ref(&self) -> &R {
+        self.rdr.get_ref().get_ref()
+    }
+}
+
+impl<R: Read> Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rdr.read(buf)
+    }
+}
+
+/// A writer that compresses records into a seekable zstd stream.
+///
+/// Create one with
+/// [`WriterBuilder::from_seekable_zstd_writer`](crate::WriterBuilder::from_seekable_zstd_writer)
+/// and call [`finish`](SeekableWriter::finish) once all records are written.
+/// If the writer is dropped without being finished, the stream is finished
+/// on a best-effort basis and any errors are ignored.
+#[derive(Debug)]
+pub struct SeekableWriter<W: io::Write> {
+    wtr: Writer<Vec<u8>>,
+    inner: Option<W>,
+    is_json_lines: bool,
+    level: i32,
+    frame_records: usize,
+    pending_records: usize,
+    frames: Vec<(u32, u32)>,
+    /// Set once finishing the stream has started, so that a failed finish
+    /// is not retried on drop, which could write a second seek table.
+    finished: bool,
+}
+
+impl<W: io::Write> Drop for SeekableWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish_stream();
+        }
+    }
+}
+
+impl<W: io::Write> SeekableWriter<W> {
+    pub(crate) fn new(builder: &WriterBuilder, wtr: W) -> SeekableWriter<W> {
+        SeekableWriter {
+            wtr: builder.from_writer(Vec::new()),
+            inner: Some(wtr),
+            is_json_lines: builder.is_json_lines,
+            level: builder.zstd_level,
+            frame_records: builder.zstd_frame_records,
+            pending_records: 0,
+            frames: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Serialize a single record using Serde.
+    ///
+    /// The record is buffered uncompressed until its frame is complete.
+    pub fn serialize<S: Serialize>(&mut self, record: S) -> Result<()> {
+        self.wtr.serialize(record)?;
+        self.pending_records += 1;
+        if self.pending_records == self.frame_records {
+            self.write_frame()?;
+            self.pending_records = 0;
+        }
+        Ok(())
+    }
+
+    /// Compress any buffered records, write the seek table and return the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_stream()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn finish_stream(&mut self) -> Result<()> {
+        self.finished = true;
+        self.wtr.close()?;
+        self.write_frame()?;
+        if self.frames.is_empty() {
+            self.write_metadata()?;
+        }
+        self.write_seek_table()?;
+        self.inner.as_mut().unwrap().flush()?;
+        Ok(())
+    }
+
+    fn write_metadata(&mut self) -> Result<()> {
+        let mut frame = Vec::with_capacity((SKIPPABLE_HEADER_SIZE as usize) + 10);
+        frame.extend_from_slice(&METADATA_FRAME_MAGIC.to_le_bytes());
+        frame.extend_from_slice(&METADATA_SIZE.to_le_bytes());
+        frame.extend_from_slice(METADATA_MAGIC);
+        frame.push(METADATA_VERSION);
+        frame.push(u8::from(self.is_json_lines));
+        frame.extend_from_slice(&frame_size(self.frame_records)?.to_le_bytes());
+        self.inner.as_mut().unwrap().write_all(&frame)?;
+        self.frames.push((frame_size(frame.len())?, 0));
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> Result<()> {
+        if self.frames.is_empty() {
+            self.write_metadata()?;
+        }
+        let buf = self.wtr.get_mut();
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let compressed = ::zstd_crate::bulk::compress(buf, self.level)?;
+        let entry = (frame_size(compressed.len())?, frame_size(buf.len())?);
+        buf.clear();
+        self.inner.as_mut().unwrap().write_all(&compressed)?;
+        self.frames.push(entry);
+        Ok(())
+    }
+
+    fn write_seek_table(&mut self) -> Result<()> {
+        let table_size = self.frames.len() * 8 + SEEK_TABLE_FOOTER_SIZE as usize;
+        let mut table = Vec::with_capacity(SKIPPABLE_HEADER_SIZE as usize + table_size);
+        table.extend_from_slice(&SEEK_TABLE_FRAME_MAGIC.to_le_bytes());
+        table.extend_from_slice(&frame_size(table_size)?.to_le_bytes());
+        for (compressed_size, decompressed_size) in &self.frames {
+            table.extend_from_slice(&compressed_size.to_le_bytes());
+            table.extend_from_slice(&decompressed_size.to_le_bytes());
+        }
+        table.extend_from_slice(&frame_size(self.frames.len())?.to_le_bytes());
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        self.inner.as_mut().unwrap().write_all(&table)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    offset: u64,
+    compressed_size: u32,
+    decompressed_size: u32,
+}
+
+/// A reader for seekable zstd streams written by a [`SeekableWriter`].
+///
+/// Unlike a [`Reader`] over a [`ZstdReader`], it decompresses only the
+/// frames covering the records that are read.
+#[derive(Debug)]
+pub struct SeekableReader<R> {
+    rdr: R,
+    is_json_lines: bool,
+    frame_records: u64,
+    frames: Vec<Frame>,
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    /// Open a seekable zstd stream by reading its seek table.
+    ///
+    /// This returns an error if `rdr` does not end with a seek table or if
+    /// the stream was not written by a [`SeekableWriter`].
+    pub fn new(mut rdr: R) -> Result<SeekableReader<R>> {
+        let end = rdr.seek(SeekFrom::End(0))?;
+        if end < SKIPPABLE_HEADER_SIZE + SEEK_TABLE_FOOTER_SIZE {
+            return Err(invalid_data("stream is too short for a seek table"));
+        }
+        let mut footer = [0; SEEK_TABLE_FOOTER_SIZE as usize];
+        rdr.seek(SeekFrom::Start(end - SEEK_TABLE_FOOTER_SIZE))?;
+        rdr.read_exact(&mut footer)?;
+        if read_u32(&footer[5..]) != SEEKABLE_MAGIC {
+            return Err(invalid_data("missing zstd seek table"));
+        }
+        let num_frames = u64::from(read_u32(&footer));
+        let entry_size = if footer[4] & CHECKSUM_FLAG == 0 {
+            8
+        } else {
+            12
+        };
+        let table_size = num_frames * entry_size + SEEK_TABLE_FOOTER_SIZE;
+        if end < SKIPPABLE_HEADER_SIZE + table_size {
+            return Err(invalid_data("truncated zstd seek table"));
+        }
+        let mut table = vec![0; (SKIPPABLE_HEADER_SIZE + table_size) as usize];
+        rdr.seek(SeekFrom::Start(end - table.len() as u64))?;
+        rdr.read_exact(&mut table)?;
+        if read_u32(&table) != SEEK_TABLE_FRAME_MAGIC
+            || u64::from(read_u32(&table[4..])) != table_size
+        {
+            return Err(invalid_data("malformed zstd seek table"));
+        }
+
+        let mut offset = 0;
+        let mut frames = Vec::with_capacity(num_frames as usize);
+        for entry in table[8..]
+            .chunks_exact(entry_size as usize)
+            .take(num_frames as usize)
+        {
+            let frame = Frame {
+                offset,
+                compressed_size: read_u32(entry),
+                decompressed_size: read_u32(&entry[4..]),
+            };
+            offset += u64::from(frame.compressed_size);
+            frames.push(frame);
+        }
+        if frames.is_empty() || frames[0].compressed_size != METADATA_SIZE + 8 {
+            return Err(invalid_data("stream was not written by json-arrays"));
+        }
+
+        let mut metadata = [0; (METADATA_SIZE + 8) as usize];
+        rdr.seek(SeekFrom::Start(0))?;
+        rdr.read_exact(&mut metadata)?;
+        if read_u32(&metadata) != METADATA_FRAME_MAGIC
+            || &metadata[8..12] != METADATA_MAGIC
+            || metadata[12] != METADATA_VERSION
+        {
+            return Err(invalid_data("stream was not written by json-arrays"));
+        }
+        let frame_records = u64::from(read_u32(&metadata[14..])).max(1);
+        frames.remove(0);
+
+        Ok(SeekableReader {
+            rdr,
+            is_json_lines: metadata[13] & 1 == 1,
+            frame_records,
+            frames,
+        })
+    }
+
+    /// Whether the compressed records are in JSON Lines format.
+    pub fn is_json_lines(&self) -> bool {
+        self.is_json_lines
+    }
+
+    /// The number of compressed frames holding records.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns a borrowed iterator over all deserialized records.
+    pub fn deserialize<D: DeserializeOwned>(&mut self) -> DeserializeRangeIter<'_, R, D> {
+        self.deserialize_range(0..u64::MAX)
+    }
+
+    /// Returns a borrowed iterator over the deserialized records with an
+    /// index in `range`.
+    ///
+    /// Only the frames covering `range` are read and decompressed.
+    pub fn deserialize_range<D: DeserializeOwned>(
+        &mut self,
+        range: Range<u64>,
+    ) -> DeserializeRangeIter<'_, R, D> {
+        let frame = range.start / self.frame_records;
+        DeserializeRangeIter {
+            record: frame * self.frame_records,
+            frame: frame.min(self.frames.len() as u64) as usize,
+            rdr: self,
+            range,
+            buf: Vec::new(),
+            pos: 0,
+            seen_array_start: false,
+            done: false,
+            _priv: PhantomData,
+        }
+    }
+
+    fn read_frame(&mut self, index: usize, buf: &mut Vec<u8>) -> Result<()> {
+        let frame = self.frames[index];
+        let mut compressed = vec![0; frame.compressed_size as usize];
+        self.rdr.seek(SeekFrom::Start(frame.offset))?;
+        self.rdr.read_exact(&mut compressed)?;
+        *buf = ::zstd_crate::bulk::decompress(&compressed, frame.decompressed_size as usize)?;
+        Ok(())
+    }
+}
+
+/// A borrowed iterator over the deserialized records of a
+/// [`SeekableReader`].
+pub struct DeserializeRangeIter<'r, R, D> {
+    rdr: &'r mut SeekableReader<R>,
+    range: Range<u64>,
+    record: u64,
+    frame: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    seen_array_start: bool,
+    done: bool,
+    _priv: PhantomData<D>,
+}
+
+impl<'r, R: Read + Seek, D: DeserializeOwned> DeserializeRangeIter<'r, R, D> {
+    /// Move `pos` to the start of the next record, loading frames as needed.
+    ///
+    /// Returns `false` when there are no more records.
+    fn next_record_start(&mut self) -> Result<bool> {
+        loop {
+            while self.pos < self.buf.len() && is_whitespace(self.buf[self.pos]) {
+                self.pos += 1;
+            }
+            if self.pos < self.buf.len() {
+                break;
+            }
+            if self.frame >= self.rdr.frames.len() {
+                return Ok(false);
+            }
+            self.rdr.read_frame(self.frame, &mut self.buf)?;
+            self.frame += 1;
+            self.pos = 0;
+        }
+        if self.rdr.is_json_lines {
+            return Ok(true);
+        }
+        if !self.seen_array_start && self.record == 0 && self.buf[self.pos] == b'[' {
+            self.seen_array_start = true;
+            self.pos += 1;
+            return self.next_record_start();
+        }
+        match self.buf[self.pos] {
+            b']' => Ok(false),
+            b',' if self.record > 0 => {
+                self.pos += 1;
+                while self.pos < self.buf.len() && is_whitespace(self.buf[self.pos]) {
+                    self.pos += 1;
+                }
+                Ok(true)
+            }
+            _ if self.record == 0 => Ok(true),
+            _ => Err(invalid_data("expected `,` between array elements")),
+        }
+    }
+
+    fn next_record<T: DeserializeOwned>(&mut self) -> Option<Result<T>> {
+        match self.next_record_start() {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some(Err(err)),
+        }
+        let mut iter = serde_json::Deserializer::from_slice(&self.buf[self.pos..]).into_iter();
+        let result = iter.next()?;
+        self.pos += iter.byte_offset();
+        self.record += 1;
+        Some(result.map_err(Error::from))
+    }
+}
+
+impl<'r, R: Read + Seek, D: DeserializeOwned> Iterator for DeserializeRangeIter<'r, R, D> {
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        if self.done {
+            return None;
+        }
+        while self.record < self.range.start {
+            match self.next_record::<IgnoredAny>() {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+        if self.record >= self.range.end {
+            self.done = true;
+            return None;
+        }
+        let result = self.next_record();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+fn frame_size(size: usize) -> Result<u32> {
+    u32::try_from(size).map_err(|_| invalid_data("zstd frame exceeds 4 GiB"))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::reader::ReaderBuilder;
+    use crate::writer::WriterBuilder;
+    use crate::zstd::SeekableReader;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Row {
+        id: u64,
+        tags: Vec<String>,
+    }
+
+    fn rows(n: u64) -> Vec<Row> {
+        (0..n)
+            .map(|id| Row {
+                id,
+                tags: vec![format!("[{}]", id), "a,b".to_string()],
+            })
+            .collect()
+    }
+
+    fn write(json_lines: bool, n: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut wtr = WriterBuilder::new()
+            .json_lines(json_lines)
+            .zstd_frame_records(3)
+            .from_seekable_zstd_writer(vec![]);
+        for row in rows(n) {
+            wtr.serialize(row)?;
+        }
+        Ok(wtr.finish()?)
+    }
+
+    #[test]
+    fn decompresses_to_plain_json() -> Result<(), Box<dyn Error>> {
+        let data = ::zstd_crate::stream::decode_all(&write(false, 7)?[..])?;
+        let expected = serde_json::to_vec(&rows(7))?;
+        assert_eq!(data, expected);
+
+        let data = ::zstd_crate::stream::decode_all(&write(true, 2)?[..])?;
+        assert_eq!(
+            String::from_utf8(data)?,
+            "{\"id\":0,\"tags\":[\"[0]\",\"a,b\"]}\n{\"id\":1,\"tags\":[\"[1]\",\"a,b\"]}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_record_ranges() -> Result<(), Box<dyn Error>> {
+        for json_lines in [false, true] {
+            let mut rdr = SeekableReader::new(Cursor::new(write(json_lines, 10)?))?;
+            assert_eq!(rdr.is_json_lines(), json_lines);
+            assert_eq!(rdr.frame_count(), 4);
+
+            let all = rdr.deserialize::<Row>().collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(all, rows(10));
+
+            let some = rdr
+                .deserialize_range::<Row>(4..8)
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(some, rows(8).split_off(4));
+
+            let tail = rdr
+                .deserialize_range::<Row>(9..100)
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(tail, rows(10).split_off(9));
+
+            assert_eq!(rdr.deserialize_range::<Row>(20..30).count(), 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_empty_stream() -> Result<(), Box<dyn Error>> {
+        let wtr = WriterBuilder::new().from_seekable_zstd_writer(vec![]);
+        let mut rdr = SeekableReader::new(Cursor::new(wtr.finish()?))?;
+        assert_eq!(rdr.deserialize::<Row>().count(), 0);
+        Ok(())
+    }
+
+    /// Fails every write after the first `ok` ones, counting the attempts.
+    struct FailAfter {
+        ok: usize,
+        attempts: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl std::io::Write for FailAfter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.attempts.set(self.attempts.get() + 1);
+            if self.attempts.get() > self.ok {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "full"));
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn does_not_retry_a_failed_finish_on_drop() -> Result<(), Box<dyn Error>> {
+        let attempts = std::rc::Rc::new(std::cell::Cell::new(0));
+        // The metadata and the frame are written; the seek table fails.
+        let mut wtr = WriterBuilder::new().from_seekable_zstd_writer(FailAfter {
+            ok: 2,
+            attempts: attempts.clone(),
+        });
+        wtr.serialize(1)?;
+        assert!(wtr.finish().is_err());
+        assert_eq!(attempts.get(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_plain_zstd_as_a_stream_only() -> Result<(), Box<dyn Error>> {
+        let data = ::zstd_crate::stream::encode_all(&b"[1,2]"[..], 0)?;
+        assert!(SeekableReader::new(Cursor::new(&data)).is_err());
+        let values = ReaderBuilder::new()
+            .from_zstd_reader(&data[..])?
+            .into_deserialize::<u32>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(values, [1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn streams_records() -> Result<(), Box<dyn Error>> {
+        for json_lines in [false, true] {
+            let mut builder = WriterBuilder::new();
+            builder.json_lines(json_lines);
+            let mut plain = builder.from_writer(vec![]);
+            let mut wtr = builder.from_zstd_writer(vec![])?;
+            for row in rows(100) {
+                plain.serialize(&row)?;
+                wtr.serialize(row)?;
+            }
+            wtr.flush()?;
+            let data = wtr.finish()?;
+            let expected = plain.into_inner()?;
+            assert_eq!(::zstd_crate::stream::decode_all(&data[..])?, expected);
+
+            let mut builder = ReaderBuilder::new();
+            builder.json_lines(json_lines).buffer_capacity(7);
+            let rdr = builder.from_zstd_reader(&data[..])?;
+            let all = rdr
+                .into_deserialize::<Row>()
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(all, rows(100));
+
+            // Seekable streams are read frame after frame.
+            let seekable = write(json_lines, 10)?;
+            let all = builder
+                .from_zstd_reader(&seekable[..])?
+                .into_deserialize::<Row>()
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(all, rows(10));
+        }
+
+        let data = WriterBuilder::new().from_zstd_writer(vec![])?.finish()?;
+        assert_eq!(::zstd_crate::stream::decode_all(&data[..])?, b"[]");
+
+        let mut data = WriterBuilder::new().from_zstd_writer(vec![])?.finish()?;
+        data.truncate(data.len() - 1);
+        let mut rdr = ReaderBuilder::new().from_zstd_reader(&data[..])?;
+        assert!(rdr.deserialize::<u32>().any(|result| result.is_err()));
+        Ok(())
+    }
+}